@@ -13,14 +13,17 @@ pub struct DS4 {
 }
 
 impl GamepadDriver for DS4Driver {
+    fn supports(&self, device_info: &hidapi::DeviceInfo) -> bool {
+        device_info.vendor_id() == HID_VENDOR_ID
+            && [HID_PRODUCT_ID_OLD, HID_PRODUCT_ID_NEW].contains(&device_info.product_id())
+    }
+
     fn init(
         &self,
         api: &HidApi,
         device_info: &hidapi::DeviceInfo,
     ) -> Result<Option<Box<dyn GamepadDevice>>> {
-        if device_info.vendor_id() == HID_VENDOR_ID
-            && [HID_PRODUCT_ID_OLD, HID_PRODUCT_ID_NEW].contains(&device_info.product_id())
-        {
+        if self.supports(device_info) {
             Ok(Some(Box::new(DS4 {
                 device: device_info.open_device(api)?,
             })))
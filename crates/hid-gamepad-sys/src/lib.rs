@@ -1,6 +1,9 @@
 pub use hid_gamepad_types::*;
 
 pub trait GamepadDriver {
+    /// Whether `init` would open this device, without opening it.
+    fn supports(&self, device_info: &hidapi::DeviceInfo) -> bool;
+
     fn init(
         &self,
         api: &hidapi::HidApi,
@@ -8,7 +11,8 @@ pub trait GamepadDriver {
     ) -> anyhow::Result<Option<Box<dyn GamepadDevice>>>;
 }
 
-pub trait GamepadDevice {
+/// Devices are usually read from a dedicated thread.
+pub trait GamepadDevice: Send {
     fn recv(&mut self) -> anyhow::Result<Report>;
     fn as_any(&mut self) -> &mut dyn std::any::Any;
 }
@@ -1,8 +1,12 @@
-use std::{ops::Mul, time::Duration};
+use std::{fmt, ops::Mul, str::FromStr, time::Duration};
 
 use cgmath::{vec3, Deg, Euler, Vector2, Vector3};
 use enum_map::{Enum, EnumMap};
 
+mod stick;
+
+pub use stick::*;
+
 #[derive(Enum, Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum JoyKey {
     Up,
@@ -27,6 +31,94 @@ pub enum JoyKey {
     Home,
 }
 
+impl JoyKey {
+    /// Every key, in declaration order.
+    pub const ALL: [JoyKey; 20] = [
+        JoyKey::Up,
+        JoyKey::Down,
+        JoyKey::Left,
+        JoyKey::Right,
+        JoyKey::N,
+        JoyKey::S,
+        JoyKey::E,
+        JoyKey::W,
+        JoyKey::L,
+        JoyKey::R,
+        JoyKey::ZL,
+        JoyKey::ZR,
+        JoyKey::SL,
+        JoyKey::SR,
+        JoyKey::L3,
+        JoyKey::R3,
+        JoyKey::Minus,
+        JoyKey::Plus,
+        JoyKey::Capture,
+        JoyKey::Home,
+    ];
+
+    /// Lowercase name, as written in configuration files.
+    pub fn name(self) -> &'static str {
+        match self {
+            JoyKey::Up => "up",
+            JoyKey::Down => "down",
+            JoyKey::Left => "left",
+            JoyKey::Right => "right",
+            JoyKey::N => "n",
+            JoyKey::S => "s",
+            JoyKey::E => "e",
+            JoyKey::W => "w",
+            JoyKey::L => "l",
+            JoyKey::R => "r",
+            JoyKey::ZL => "zl",
+            JoyKey::ZR => "zr",
+            JoyKey::SL => "sl",
+            JoyKey::SR => "sr",
+            JoyKey::L3 => "l3",
+            JoyKey::R3 => "r3",
+            JoyKey::Minus => "minus",
+            JoyKey::Plus => "plus",
+            JoyKey::Capture => "capture",
+            JoyKey::Home => "home",
+        }
+    }
+}
+
+impl fmt::Display for JoyKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parses the names returned by `JoyKey::name`, ignoring the case.
+impl FromStr for JoyKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let lower = s.to_ascii_lowercase();
+        JoyKey::ALL
+            .iter()
+            .copied()
+            .find(|key| key.name() == lower)
+            .ok_or_else(|| {
+                let names: Vec<_> = JoyKey::ALL.iter().map(|key| key.name()).collect();
+                format!(
+                    "unknown button `{}` (expected one of: {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for JoyKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum KeyStatus {
     Pressed,
@@ -133,3 +225,12 @@ impl From<Vector3<f64>> for Acceleration {
         }
     }
 }
+
+#[test]
+fn key_names() {
+    for key in JoyKey::ALL.iter() {
+        assert_eq!(key.name().parse(), Ok(*key));
+    }
+    assert_eq!("ZR".parse(), Ok(JoyKey::ZR));
+    assert!("a".parse::<JoyKey>().is_err());
+}
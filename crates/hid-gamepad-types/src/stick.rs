@@ -0,0 +1,184 @@
+use cgmath::{InnerSpace, Vector2};
+
+/// How the deadzones are applied to the two axis of a stick.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum DeadzoneShape {
+    /// On the distance from the center, keeps the direction intact.
+    #[default]
+    Radial,
+    /// On each axis separately, snaps to the axis near the center.
+    Axial,
+}
+
+/// Deadzones and response curve of an analog stick.
+///
+/// Positions are between 0 (center) and 1 (edge).
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct StickResponse {
+    pub shape: DeadzoneShape,
+    /// Positions closer to the center are ignored, to hide the stick drift.
+    pub inner_deadzone: f64,
+    /// Positions further from the center are considered at the edge, for
+    /// sticks that don't reach 1.
+    pub outer_deadzone: f64,
+    /// Smallest output right out of the inner deadzone, to cancel the
+    /// deadzone of a game.
+    pub anti_deadzone: f64,
+    /// Exponent of the response curve, 1 is linear and higher values give
+    /// more precision near the center.
+    pub curve: f64,
+}
+
+impl StickResponse {
+    /// Checks that the values are in range, and returns a description of the
+    /// first invalid one.
+    ///
+    /// The checks are written so that NaN fails them, TOML accepts `nan` and `inf`.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0. ..1.).contains(&self.inner_deadzone) {
+            return Err(format!(
+                "inner_deadzone must be between 0 and 1 (got {})",
+                self.inner_deadzone
+            ));
+        }
+        if !(self.outer_deadzone > self.inner_deadzone && self.outer_deadzone <= 1.) {
+            return Err(format!(
+                "outer_deadzone must be between inner_deadzone and 1 (got {})",
+                self.outer_deadzone
+            ));
+        }
+        if !(0. ..1.).contains(&self.anti_deadzone) {
+            return Err(format!(
+                "anti_deadzone must be between 0 and 1 (got {})",
+                self.anti_deadzone
+            ));
+        }
+        if !(self.curve > 0. && self.curve.is_finite()) {
+            return Err(format!("curve must be positive (got {})", self.curve));
+        }
+        Ok(())
+    }
+
+    /// Applies the deadzones and the curve to a stick position.
+    pub fn apply(&self, stick: Vector2<f64>) -> Vector2<f64> {
+        match self.shape {
+            DeadzoneShape::Radial => {
+                let magnitude = stick.magnitude();
+                if magnitude == 0. {
+                    stick
+                } else {
+                    stick * (self.apply_magnitude(magnitude) / magnitude)
+                }
+            }
+            DeadzoneShape::Axial => Vector2::new(
+                self.apply_magnitude(stick.x.abs()).copysign(stick.x),
+                self.apply_magnitude(stick.y.abs()).copysign(stick.y),
+            ),
+        }
+    }
+
+    fn apply_magnitude(&self, magnitude: f64) -> f64 {
+        if magnitude <= self.inner_deadzone {
+            return 0.;
+        }
+        let t = ((magnitude - self.inner_deadzone) / (self.outer_deadzone - self.inner_deadzone))
+            .min(1.)
+            .powf(self.curve);
+        self.anti_deadzone + (1. - self.anti_deadzone) * t
+    }
+}
+
+impl Default for StickResponse {
+    fn default() -> Self {
+        StickResponse {
+            shape: DeadzoneShape::Radial,
+            inner_deadzone: 0.15,
+            outer_deadzone: 0.95,
+            anti_deadzone: 0.,
+            curve: 1.,
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_near(actual: Vector2<f64>, expected: Vector2<f64>) {
+    assert!((actual - expected).magnitude() < 1e-9, "{:?}", actual);
+}
+
+#[test]
+fn radial() {
+    use cgmath::vec2;
+
+    let response = StickResponse {
+        inner_deadzone: 0.2,
+        outer_deadzone: 0.8,
+        ..StickResponse::default()
+    };
+    assert_near(response.apply(vec2(0.1, -0.1)), vec2(0., 0.));
+    // A slow diagonal keeps its direction.
+    assert_near(response.apply(vec2(0.36, 0.48)), vec2(0.6, 0.8) * (2. / 3.));
+    assert_near(response.apply(vec2(0., -0.9)), vec2(0., -1.));
+
+    let response = StickResponse {
+        anti_deadzone: 0.25,
+        curve: 2.,
+        ..response
+    };
+    assert_near(
+        response.apply(vec2(0.2 + 0.6 / 2., 0.)),
+        vec2(0.25 + 0.75 / 4., 0.),
+    );
+}
+
+#[test]
+fn axial() {
+    use cgmath::vec2;
+
+    let response = StickResponse {
+        shape: DeadzoneShape::Axial,
+        inner_deadzone: 0.2,
+        outer_deadzone: 1.,
+        ..StickResponse::default()
+    };
+    assert_near(response.apply(vec2(0.1, -0.6)), vec2(0., -0.5));
+    assert!(StickResponse {
+        outer_deadzone: 0.1,
+        ..response
+    }
+    .validate()
+    .is_err());
+}
+
+#[test]
+fn not_a_number() {
+    let invalid = [
+        StickResponse {
+            curve: f64::NAN,
+            ..StickResponse::default()
+        },
+        StickResponse {
+            curve: f64::INFINITY,
+            ..StickResponse::default()
+        },
+        StickResponse {
+            outer_deadzone: f64::NAN,
+            ..StickResponse::default()
+        },
+        StickResponse {
+            inner_deadzone: f64::NAN,
+            ..StickResponse::default()
+        },
+        StickResponse {
+            anti_deadzone: f64::NAN,
+            ..StickResponse::default()
+        },
+    ];
+    for response in invalid.iter() {
+        assert!(response.validate().is_err(), "{:?}", response);
+    }
+    assert!(StickResponse::default().validate().is_ok());
+}
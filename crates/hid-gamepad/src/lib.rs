@@ -9,13 +9,20 @@ pub use hid_gamepad_sys as sys;
 pub use error::*;
 use joycon::{JoyCon, JoyconDriver};
 
+fn drivers() -> Vec<Box<dyn GamepadDriver>> {
+    vec![Box::new(DS4Driver), Box::new(JoyconDriver)]
+}
+
+/// Whether one of the drivers can open this device.
+pub fn is_supported(device_info: &DeviceInfo) -> bool {
+    drivers().iter().any(|driver| driver.supports(device_info))
+}
+
 pub fn open_gamepad(
     api: &HidApi,
     device_info: &DeviceInfo,
 ) -> Result<Option<Box<dyn GamepadDevice + 'static>>> {
-    let mut drivers: Vec<Box<dyn GamepadDriver>> =
-        vec![Box::new(DS4Driver), Box::new(JoyconDriver)];
-    for driver in drivers.drain(..) {
+    for driver in drivers() {
         if let Some(device) = driver.init(api, device_info)? {
             return Ok(Some(device));
         }
@@ -1,5 +1,5 @@
 use num::FromPrimitive;
-use std::fmt;
+use std::{fmt, ops::BitOr};
 
 bitfield::bitfield! {
     #[repr(transparent)]
@@ -55,6 +55,20 @@ pub struct ButtonsStatus {
     pub left: LeftButtons,
 }
 
+/// Combines the buttons of a left and a right Joy-Con, which each only set
+/// their own bits.
+impl BitOr for ButtonsStatus {
+    type Output = ButtonsStatus;
+
+    fn bitor(self, other: ButtonsStatus) -> ButtonsStatus {
+        ButtonsStatus {
+            right: RightButtons(self.right.0 | other.right.0),
+            middle: MiddleButtons(self.middle.0 | other.middle.0),
+            left: LeftButtons(self.left.0 | other.left.0),
+        }
+    }
+}
+
 impl fmt::Debug for ButtonsStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_tuple("ButtonsStatus")
@@ -134,26 +148,26 @@ bitfield::bitfield! {
     #[derive(Copy, Clone, Default)]
     pub struct RightButtons(u8);
     impl Debug;
-    pub y, _: 0;
-    pub x, _: 1;
-    pub b, _: 2;
-    pub a, _: 3;
-    pub sr, _: 4;
-    pub sl, _: 5;
-    pub r, _: 6;
-    pub zr, _: 7;
+    pub y, set_y: 0;
+    pub x, set_x: 1;
+    pub b, set_b: 2;
+    pub a, set_a: 3;
+    pub sr, set_sr: 4;
+    pub sl, set_sl: 5;
+    pub r, set_r: 6;
+    pub zr, set_zr: 7;
 }
 bitfield::bitfield! {
     #[repr(transparent)]
     #[derive(Copy, Clone, Default)]
     pub struct MiddleButtons(u8);
     impl Debug;
-    pub minus, _: 0;
-    pub plus, _: 1;
-    pub rstick, _: 2;
-    pub lstick, _: 3;
-    pub home, _: 4;
-    pub capture, _: 5;
+    pub minus, set_minus: 0;
+    pub plus, set_plus: 1;
+    pub rstick, set_rstick: 2;
+    pub lstick, set_lstick: 3;
+    pub home, set_home: 4;
+    pub capture, set_capture: 5;
     pub _unused, _: 6;
     pub charging_grip, _: 7;
 }
@@ -163,14 +177,14 @@ bitfield::bitfield! {
     #[derive(Copy, Clone, Default)]
     pub struct LeftButtons(u8);
     impl Debug;
-    pub down, _: 0;
-    pub up, _: 1;
-    pub right, _: 2;
-    pub left, _: 3;
-    pub sr, _: 4;
-    pub sl, _: 5;
-    pub l, _: 6;
-    pub zl, _: 7;
+    pub down, set_down: 0;
+    pub up, set_up: 1;
+    pub right, set_right: 2;
+    pub left, set_left: 3;
+    pub sr, set_sr: 4;
+    pub sl, set_sl: 5;
+    pub l, set_l: 6;
+    pub zl, set_zl: 7;
 }
 
 pub enum Button {
@@ -214,3 +228,21 @@ impl fmt::Debug for Stick {
             .finish()
     }
 }
+
+#[test]
+fn merge_buttons() {
+    let left = ButtonsStatus {
+        left: LeftButtons(0b0100_0010),
+        middle: MiddleButtons(0b0000_1001),
+        ..ButtonsStatus::default()
+    };
+    let right = ButtonsStatus {
+        right: RightButtons(0b0000_1000),
+        middle: MiddleButtons(0b0001_0000),
+        ..ButtonsStatus::default()
+    };
+    let both = left | right;
+    assert!(both.left.up() && both.left.l() && both.right.a());
+    assert!(both.middle.minus() && both.middle.lstick() && both.middle.home());
+    assert!(!both.right.b() && !both.middle.plus());
+}
@@ -29,6 +29,10 @@ impl Calibration {
         self.history.clear();
     }
 
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
     pub fn get_average(&mut self) -> Entry {
         let zero = Vector3::new(0., 0., 0.);
         let len = self.history.len() as f64;
@@ -48,3 +52,104 @@ impl Default for Calibration {
         Calibration::with_capacity(3 * IMU_SAMPLES_PER_SECOND as usize)
     }
 }
+
+/// Detects when the controller is lying still, so that the gyroscope bias can
+/// be measured.
+///
+/// The controller is still when the gyroscope barely varies and the
+/// accelerometer only measures gravity over the whole window.
+#[derive(Clone, Debug)]
+pub struct Stillness {
+    gyro: VecDeque<Entry>,
+    accel: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl Stillness {
+    /// Maximum variance of each gyroscope axis over the window, in dps².
+    pub const GYRO_VARIANCE: f64 = 0.2;
+    /// Maximum mean rotation speed, in dps. Anything faster is a slow
+    /// rotation rather than a bias.
+    pub const GYRO_MAX_BIAS: f64 = 5.;
+    /// Maximum distance of the acceleration from 1g.
+    pub const ACCEL_TOLERANCE: f64 = 0.1;
+
+    pub fn with_capacity(capacity: usize) -> Stillness {
+        Stillness {
+            gyro: VecDeque::with_capacity(capacity),
+            accel: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Adds a sample and returns whether the controller was still over the
+    /// whole window.
+    pub fn push(&mut self, gyro: Entry, accel: Entry) -> bool {
+        if self.gyro.len() == self.capacity {
+            self.gyro.pop_back();
+            self.accel.pop_back();
+        }
+        self.gyro.push_front(gyro);
+        self.accel.push_front(accel.magnitude());
+        self.is_still()
+    }
+
+    pub fn is_still(&self) -> bool {
+        if self.gyro.len() < self.capacity {
+            return false;
+        }
+        if self
+            .accel
+            .iter()
+            .any(|norm| (norm - 1.).abs() > Self::ACCEL_TOLERANCE)
+        {
+            return false;
+        }
+        let len = self.gyro.len() as f64;
+        let mean = self.gyro.iter().fold(Vector3::zero(), |acc, val| acc + val) / len;
+        let variance = self
+            .gyro
+            .iter()
+            .map(|val| (val - mean).map(|x| x * x))
+            .fold(Vector3::zero(), |acc, val| acc + val)
+            / len;
+        mean.magnitude() < Self::GYRO_MAX_BIAS
+            && variance.x.max(variance.y).max(variance.z) < Self::GYRO_VARIANCE
+    }
+
+    pub fn reset(&mut self) {
+        self.gyro.clear();
+        self.accel.clear();
+    }
+}
+
+impl Default for Stillness {
+    fn default() -> Self {
+        Stillness::with_capacity(IMU_SAMPLES_PER_SECOND as usize / 2)
+    }
+}
+
+#[test]
+fn stillness() {
+    let mut stillness = Stillness::with_capacity(4);
+    let bias = Vector3::new(1.5, -0.5, 0.2);
+    let gravity = Vector3::new(0., 0., 1.);
+    for _ in 0..3 {
+        assert!(!stillness.push(bias, gravity));
+    }
+    assert!(stillness.push(bias, gravity));
+
+    // Being moved.
+    assert!(!stillness.push(bias + Vector3::new(20., 0., 0.), gravity));
+    stillness.reset();
+    // Rotating slowly but steadily.
+    for _ in 0..4 {
+        stillness.push(Vector3::new(10., 0., 0.), gravity);
+    }
+    assert!(!stillness.is_still());
+    // Shaken without rotating.
+    for _ in 0..4 {
+        stillness.push(bias, gravity * 1.5);
+    }
+    assert!(!stillness.is_still());
+}
@@ -0,0 +1,177 @@
+use anyhow::bail;
+use cgmath::{vec2, vec3, Vector2, Vector3, Zero};
+use joycon_sys::input::{ButtonsStatus, WhichController};
+use std::{fmt, str::FromStr};
+
+/// How a single Joy-Con is held.
+///
+/// Sideways, the rail faces away from the player with the stick on the left,
+/// like a small gamepad: the stick and IMU axes are rotated to match, and SL
+/// and SR become the main shoulder buttons. It has no effect on a Pro
+/// Controller.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Grip {
+    #[default]
+    Vertical,
+    Sideways,
+}
+
+impl Grip {
+    pub fn name(self) -> &'static str {
+        match self {
+            Grip::Vertical => "vertical",
+            Grip::Sideways => "sideways",
+        }
+    }
+
+    fn sideways(self, device: WhichController) -> bool {
+        self == Grip::Sideways && device != WhichController::ProController
+    }
+
+    /// Rotates the calibrated sticks.
+    ///
+    /// A single Joy-Con only has one stick and reads garbage for the other,
+    /// which is zeroed. Sideways, the stick is reported as the left one.
+    pub fn sticks(
+        self,
+        device: WhichController,
+        left: Vector2<f64>,
+        right: Vector2<f64>,
+    ) -> (Vector2<f64>, Vector2<f64>) {
+        match (device, self.sideways(device)) {
+            (WhichController::ProController, _) => (left, right),
+            (WhichController::LeftJoyCon, false) => (left, Vector2::zero()),
+            (WhichController::RightJoyCon, false) => (Vector2::zero(), right),
+            // Turned a quarter counterclockwise, right is now up.
+            (WhichController::LeftJoyCon, true) => (vec2(-left.y, left.x), Vector2::zero()),
+            // Turned a quarter clockwise, left is now up.
+            (WhichController::RightJoyCon, true) => (vec2(right.y, -right.x), Vector2::zero()),
+        }
+    }
+
+    /// Rotates a gyroscope or accelerometer sample, in the axes of `IMU`.
+    pub fn imu(self, device: WhichController, v: Vector3<f64>) -> Vector3<f64> {
+        match (device, self.sideways(device)) {
+            (WhichController::LeftJoyCon, true) => vec3(v.y, -v.x, v.z),
+            (WhichController::RightJoyCon, true) => vec3(-v.y, v.x, v.z),
+            _ => v,
+        }
+    }
+
+    /// Moves the buttons to where they are when held this way.
+    pub fn buttons(self, device: WhichController, buttons: ButtonsStatus) -> ButtonsStatus {
+        if !self.sideways(device) {
+            return buttons;
+        }
+        let b = buttons;
+        let mut out = ButtonsStatus {
+            middle: b.middle,
+            ..ButtonsStatus::default()
+        };
+        // The buttons under the stick become the face buttons, and the
+        // shoulder buttons of the vertical grip become the second row.
+        match device {
+            WhichController::LeftJoyCon => {
+                out.right.set_x(b.left.right());
+                out.right.set_a(b.left.down());
+                out.right.set_b(b.left.left());
+                out.right.set_y(b.left.up());
+                out.left.set_zl(b.left.l());
+                out.right.set_zr(b.left.zl());
+                out.left.set_l(b.left.sl());
+                out.right.set_r(b.left.sr());
+            }
+            _ => {
+                out.right.set_x(b.right.y());
+                out.right.set_a(b.right.x());
+                out.right.set_b(b.right.a());
+                out.right.set_y(b.right.b());
+                out.left.set_zl(b.right.r());
+                out.right.set_zr(b.right.zr());
+                out.middle.set_lstick(b.middle.rstick());
+                out.middle.set_rstick(false);
+                out.left.set_l(b.right.sl());
+                out.right.set_r(b.right.sr());
+            }
+        }
+        out
+    }
+}
+
+impl fmt::Display for Grip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Grip {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "vertical" => Ok(Grip::Vertical),
+            "sideways" => Ok(Grip::Sideways),
+            _ => bail!("unknown grip `{}` (expected `vertical` or `sideways`)", s),
+        }
+    }
+}
+
+#[test]
+fn sideways_axes() {
+    use crate::IMU;
+    use cgmath::InnerSpace;
+
+    for device in [WhichController::LeftJoyCon, WhichController::RightJoyCon].iter() {
+        // The side of the rail becomes up: right for the left Joy-Con, left
+        // for the right one.
+        let rail = if *device == WhichController::LeftJoyCon {
+            1.
+        } else {
+            -1.
+        };
+        let (left, right) = Grip::Sideways.sticks(*device, vec2(rail, 0.), vec2(rail, 0.));
+        assert_eq!(left, vec2(0., 1.));
+        assert_eq!(right, Vector2::zero());
+
+        // Same for the IMU: up on the stick is forward (-Z) in the SDL convention.
+        let imu = IMU {
+            gyro: Vector3::zero(),
+            accel: vec3(0., -rail, 0.),
+        };
+        assert_eq!(imu.sdl_accel(), vec3(rail, 0., 0.));
+        let rotated = IMU {
+            accel: Grip::Sideways.imu(*device, imu.accel),
+            ..imu
+        };
+        assert!((rotated.sdl_accel() - vec3(0., 0., -1.)).magnitude() < 1e-9);
+    }
+    assert_eq!(
+        Grip::Sideways.sticks(WhichController::ProController, vec2(1., 0.), vec2(0., 1.)),
+        (vec2(1., 0.), vec2(0., 1.))
+    );
+}
+
+#[test]
+fn sideways_buttons() {
+    let mut buttons = ButtonsStatus::default();
+    buttons.left.set_sl(true);
+    buttons.left.set_down(true);
+    buttons.middle.set_minus(true);
+    let buttons = Grip::Sideways.buttons(WhichController::LeftJoyCon, buttons);
+    // Down becomes E and SL becomes L.
+    assert!(buttons.right.a() && buttons.left.l() && buttons.middle.minus());
+    assert!(!buttons.left.down() && !buttons.left.sl());
+
+    let mut buttons = ButtonsStatus::default();
+    buttons.right.set_x(true);
+    buttons.middle.set_rstick(true);
+    let buttons = Grip::Sideways.buttons(WhichController::RightJoyCon, buttons);
+    // N becomes E, R3 becomes L3.
+    assert!(buttons.right.a() && buttons.middle.lstick());
+    assert!(!buttons.right.x() && !buttons.middle.rstick());
+
+    let mut buttons = ButtonsStatus::default();
+    buttons.left.set_up(true);
+    let buttons = Grip::Vertical.buttons(WhichController::LeftJoyCon, buttons);
+    assert!(buttons.left.up());
+}
@@ -1,8 +1,8 @@
 use std::convert::TryInto;
 
-use crate::imu_handler;
+use crate::{imu_handler, Grip};
 use anyhow::{bail, ensure, Context, Result};
-use cgmath::Vector2;
+use cgmath::{Quaternion, Vector2, Vector3};
 use joycon_sys::mcu::*;
 use joycon_sys::output::*;
 use joycon_sys::spi::*;
@@ -23,6 +23,13 @@ pub struct Report {
     #[cfg(feature = "ir")]
     pub image: Option<image::GrayImage>,
     pub imu: Option<[imu_handler::IMU; 3]>,
+    /// Rotation from the controller frame to the world frame, see `Orientation`.
+    pub orientation: Quaternion<f64>,
+    /// Unit vector pointing to the ground in the controller frame, see `Orientation`.
+    pub gravity: Vector3<f64>,
+    /// Set once when the gyroscope bias has been measured again while the
+    /// controller was still.
+    pub gyro_recalibrated: bool,
     pub raw: InputReport,
 }
 
@@ -38,7 +45,9 @@ pub struct JoyCon {
     image: crate::image::Image,
     enable_ir_loop: bool,
     imu_handler: crate::imu_handler::Handler,
+    last_imu: Option<[imu_handler::IMU; 3]>,
     device_type: WhichController,
+    grip: Grip,
 }
 
 impl JoyCon {
@@ -67,7 +76,9 @@ impl JoyCon {
                 imu::GyroSens::default(),
                 imu::AccSens::default(),
             ),
+            last_imu: None,
             device_type,
+            grip: Grip::default(),
         };
 
         joycon.call_subcmd_wait(SubcommandRequest::disable_shipment_mode())?;
@@ -75,6 +86,20 @@ impl JoyCon {
         Ok(joycon)
     }
 
+    pub fn device_type(&self) -> WhichController {
+        self.device_type
+    }
+
+    pub fn grip(&self) -> Grip {
+        self.grip
+    }
+
+    /// Rotates the sticks and the IMU for the way the Joy-Con is held.
+    pub fn set_grip(&mut self, grip: Grip) {
+        self.grip = grip;
+        self.imu_handler.set_grip(grip);
+    }
+
     pub fn supports_ir(&self) -> bool {
         self.device_type == WhichController::RightJoyCon
     }
@@ -90,19 +115,34 @@ impl JoyCon {
         Ok(())
     }
 
-    #[instrument(level = "trace", skip(self), fields(special, report))]
     pub fn recv(&mut self) -> Result<InputReport> {
+        loop {
+            if let Some(report) = self.recv_timeout(-1)? {
+                return Ok(report);
+            }
+        }
+    }
+
+    /// Like `recv`, but gives up after `timeout` milliseconds (`0` only takes
+    /// a report that has already arrived, `-1` waits forever).
+    #[instrument(level = "trace", skip(self), fields(special, report))]
+    pub fn recv_timeout(&mut self, timeout: i32) -> Result<Option<InputReport>> {
         let mut report = InputReport::new();
-        let nb_read = self.device.read(report.as_bytes_mut())?;
+        let nb_read = self.device.read_timeout(report.as_bytes_mut(), timeout)?;
+        if nb_read == 0 {
+            return Ok(None);
+        }
         assert!(nb_read >= report.len(), "{} < {}", nb_read, report.len());
         Span::current()
             .record("special", &report.is_special())
             .record("report", &debug(report));
         trace!(in__report = %hex::encode(report.as_bytes()));
         report.validate();
-        if let Some(frames) = report.imu_frames() {
-            self.imu_handler.handle_frames(frames);
-        }
+        // Every frame has to go through the handler exactly once to keep the
+        // orientation right.
+        self.last_imu = report
+            .imu_frames()
+            .map(|frames| self.imu_handler.handle_frames(frames));
         #[cfg(feature = "ir")]
         if let Some(mcu_report) = report.mcu_report() {
             if self.enable_ir_loop {
@@ -111,7 +151,16 @@ impl JoyCon {
                 }
             }
         }
-        Ok(report)
+        Ok(Some(report))
+    }
+
+    /// The rumble motors only react to `set_rumble` once enabled.
+    #[instrument(level = "info", skip(self), err)]
+    pub fn enable_vibration(&mut self, enable: bool) -> Result<()> {
+        self.call_subcmd_wait(SubcommandRequestEnum::EnableVibration(
+            Bool::from(enable).into(),
+        ))?;
+        Ok(())
     }
 
     pub fn set_rumble(&mut self, rumble: RumbleData) -> Result<()> {
@@ -121,6 +170,17 @@ impl JoyCon {
 
     pub fn tick(&mut self) -> Result<Report> {
         let report = self.recv()?;
+        Ok(self.report(report))
+    }
+
+    /// Like `tick`, but gives up after `timeout` milliseconds, see `recv_timeout`.
+    pub fn try_tick(&mut self, timeout: i32) -> Result<Option<Report>> {
+        Ok(self
+            .recv_timeout(timeout)?
+            .map(|report| self.report(report)))
+    }
+
+    fn report(&mut self, report: InputReport) -> Report {
         let std_report = report.standard().expect("should be standard");
 
         let left_stick = self
@@ -129,19 +189,21 @@ impl JoyCon {
         let right_stick = self
             .right_stick_calib
             .value_from_raw(std_report.right_stick.x(), std_report.right_stick.y());
+        let (left_stick, right_stick) = self.grip.sticks(self.device_type, left_stick, right_stick);
 
-        Ok(Report {
+        Report {
             left_stick,
             right_stick,
-            buttons: std_report.buttons,
+            buttons: self.grip.buttons(self.device_type, std_report.buttons),
             info: std_report.info,
             #[cfg(feature = "ir")]
             image: self.image.last_image.take(),
-            imu: report
-                .imu_frames()
-                .map(|f| self.imu_handler.handle_frames(f)),
+            imu: self.last_imu,
+            orientation: self.imu_handler.orientation().quaternion(),
+            gravity: self.imu_handler.orientation().gravity(),
+            gyro_recalibrated: self.imu_handler.take_recalibrated(),
             raw: report,
-        })
+        }
     }
 
     pub fn load_calibration(&mut self) -> Result<()> {
@@ -1,4 +1,8 @@
-use crate::calibration::Calibration;
+use crate::{
+    calibration::{Calibration, Stillness},
+    orientation::Orientation,
+    Grip,
+};
 use cgmath::*;
 use input::WhichController;
 use joycon_sys::*;
@@ -19,6 +23,16 @@ pub struct IMU {
 impl IMU {
     pub const SAMPLE_DURATION: f64 = imu::IMU_SAMPLE_DURATION;
     pub const SAMPLE_PER_SECOND: u32 = imu::IMU_SAMPLES_PER_SECOND;
+
+    /// Acceleration in the SDL convention, see `hid_gamepad_sys::Acceleration`.
+    pub fn sdl_accel(&self) -> Vector3<f64> {
+        vec3(-self.accel.y, self.accel.z, self.accel.x)
+    }
+
+    /// Rotation speed in the SDL convention, see `hid_gamepad_sys::RotationSpeed`.
+    pub fn sdl_gyro(&self) -> Vector3<f64> {
+        vec3(self.gyro.y, -self.gyro.z, -self.gyro.x)
+    }
 }
 
 pub struct Handler {
@@ -28,7 +42,14 @@ pub struct Handler {
     accel_sens: imu::AccSens,
     factory_calibration: spi::SensorCalibration,
     user_calibration: spi::UserSensorCalibration,
+    /// Number of samples left to push in `calib_gyro` for the current
+    /// recalibration.
     calib_nb: u32,
+    stillness: Stillness,
+    still: bool,
+    recalibrated: bool,
+    orientation: Orientation,
+    grip: Grip,
 }
 
 impl Handler {
@@ -45,9 +66,20 @@ impl Handler {
             factory_calibration: spi::SensorCalibration::default(),
             user_calibration: spi::UserSensorCalibration::default(),
             calib_nb: 0,
+            stillness: Stillness::default(),
+            still: false,
+            recalibrated: false,
+            orientation: Orientation::new(),
+            grip: Grip::default(),
         }
     }
 
+    /// The orientation starts over from the accelerometer, the axes changed.
+    pub fn set_grip(&mut self, grip: Grip) {
+        self.grip = grip;
+        self.orientation.reset();
+    }
+
     pub fn set_factory(&mut self, calib: spi::SensorCalibration) {
         self.factory_calibration = calib;
     }
@@ -78,9 +110,21 @@ impl Handler {
         for (frame, out) in frames.iter().rev().zip(out.iter_mut()) {
             let raw_rotation = frame.rotation_dps(gyro_offset, self.gyro_sens);
             let raw_acc = frame.accel_g(acc_offset, self.accel_sens);
+            // Measure the bias again every time the controller is put down,
+            // it drifts with the temperature.
+            let still = self.stillness.push(raw_rotation, raw_acc);
+            if still && !self.still {
+                self.calib_nb = self.calib_gyro.capacity() as u32;
+            } else if !still {
+                self.calib_nb = 0;
+            }
+            self.still = still;
             if self.calib_nb > 0 {
                 self.calib_gyro.push(raw_rotation);
                 self.calib_nb -= 1;
+                if self.calib_nb == 0 {
+                    self.recalibrated = true;
+                }
             }
             *out = IMU {
                 gyro: raw_rotation - self.calib_gyro.get_average(),
@@ -97,12 +141,28 @@ impl Handler {
                     out.accel = -out.accel;
                 }
             }
+            out.gyro = self.grip.imu(self.device_type, out.gyro);
+            out.accel = self.grip.imu(self.device_type, out.accel);
+            self.orientation.update(out, IMU::SAMPLE_DURATION);
         }
         out
     }
 
+    pub fn orientation(&self) -> &Orientation {
+        &self.orientation
+    }
+
+    /// Returns whether the gyroscope bias was measured again since the last
+    /// call.
+    pub fn take_recalibrated(&mut self) -> bool {
+        std::mem::take(&mut self.recalibrated)
+    }
+
     pub fn reset_calibration(&mut self) {
         self.calib_gyro.reset();
         self.calib_nb = 0;
+        self.stillness.reset();
+        self.still = false;
+        self.recalibrated = false;
     }
 }
@@ -1,32 +1,45 @@
 mod calibration;
+mod grip;
 mod hid;
 #[cfg(feature = "ir")]
 mod image;
 mod imu_handler;
+mod orientation;
+mod pair;
 
 #[cfg(feature = "ir")]
 pub use crate::image::*;
 use anyhow::Result;
 pub use calibration::*;
-use cgmath::vec3;
+pub use grip::Grip;
 pub use hid::*;
 use hid_gamepad_sys::{GamepadDevice, GamepadDriver, JoyKey, Motion};
 use hidapi::HidApi;
 pub use imu_handler::IMU;
 pub use joycon_sys;
+pub use orientation::Orientation;
+pub use pair::*;
 
 pub use hidapi;
-use joycon_sys::{imu::IMU_SAMPLES_PER_SECOND, NINTENDO_VENDOR_ID};
+use joycon_sys::{
+    imu::IMU_SAMPLES_PER_SECOND, JOYCON_L_BT, JOYCON_R_BT, NINTENDO_VENDOR_ID, PRO_CONTROLLER,
+};
 
 pub struct JoyconDriver;
 
 impl GamepadDriver for JoyconDriver {
+    /// Joy-Cons and the Pro Controller, but not the charging grip.
+    fn supports(&self, device_info: &hidapi::DeviceInfo) -> bool {
+        device_info.vendor_id() == NINTENDO_VENDOR_ID
+            && [JOYCON_L_BT, JOYCON_R_BT, PRO_CONTROLLER].contains(&device_info.product_id())
+    }
+
     fn init(
         &self,
         api: &HidApi,
         device_info: &hidapi::DeviceInfo,
     ) -> Result<Option<Box<dyn GamepadDevice>>> {
-        if device_info.vendor_id() == NINTENDO_VENDOR_ID {
+        if self.supports(device_info) {
             let mut joycon = JoyCon::new(device_info.open_device(api)?, device_info.clone())?;
             joycon.enable_imu()?;
             joycon.load_calibration()?;
@@ -47,6 +60,16 @@ impl GamepadDevice for JoyCon {
     }
 }
 
+impl GamepadDevice for JoyConPair {
+    fn recv(&mut self) -> Result<hid_gamepad_sys::Report> {
+        Ok(self.tick()?.merged.into())
+    }
+
+    fn as_any(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
 impl From<Report> for hid_gamepad_sys::Report {
     fn from(report: Report) -> Self {
         let b = &report.buttons;
@@ -55,11 +78,11 @@ impl From<Report> for hid_gamepad_sys::Report {
             right_joystick: report.right_stick,
             motion: report
                 .imu
-                .unwrap()
                 .iter()
+                .flatten()
                 .map(|x| Motion {
-                    acceleration: vec3(-x.accel.y, x.accel.z, x.accel.x).into(),
-                    rotation_speed: vec3(x.gyro.y, -x.gyro.z, -x.gyro.x).into(),
+                    acceleration: x.sdl_accel().into(),
+                    rotation_speed: x.sdl_gyro().into(),
                 })
                 .collect(),
             keys: enum_map::enum_map! {
@@ -0,0 +1,147 @@
+use crate::IMU;
+use cgmath::*;
+use hid_gamepad_sys::Motion;
+
+/// Orientation of the controller, estimated from the gyroscope and the
+/// accelerometer with a Mahony filter.
+///
+/// Uses the SDL convention of `hid_gamepad_sys::Motion` (+Y is up when the
+/// controller lies flat).
+///
+/// <https://x-io.co.uk/open-source-imu-and-ahrs-algorithms/>
+#[derive(Debug, Clone)]
+pub struct Orientation {
+    /// Rotation from the controller frame to the world frame.
+    quat: Quaternion<f64>,
+    integral: Vector3<f64>,
+    initialized: bool,
+    /// Proportional gain: how fast the accelerometer corrects the gyroscope drift.
+    pub kp: f64,
+    /// Integral gain: how fast the gyroscope bias is compensated. Disabled if 0.
+    pub ki: f64,
+}
+
+impl Orientation {
+    /// Accelerometer samples further than this from 1g are ignored, the
+    /// controller is being shaken.
+    const ACCEL_TOLERANCE: f64 = 0.5;
+
+    pub fn new() -> Self {
+        Orientation {
+            quat: Quaternion::one(),
+            integral: Vector3::zero(),
+            initialized: false,
+            kp: 0.5,
+            ki: 0.,
+        }
+    }
+
+    /// Integrates one IMU sample lasting `dt` seconds.
+    pub fn update(&mut self, imu: &IMU, dt: f64) {
+        self.update_sdl(imu.sdl_gyro(), imu.sdl_accel(), dt);
+    }
+
+    /// Integrates one motion sample of any gamepad lasting `dt` seconds.
+    pub fn update_motion(&mut self, motion: &Motion, dt: f64) {
+        self.update_sdl(
+            motion.rotation_speed.as_vec(),
+            motion.acceleration.as_vec(),
+            dt,
+        );
+    }
+
+    fn update_sdl(&mut self, gyro: Vector3<f64>, accel: Vector3<f64>, dt: f64) {
+        let norm = accel.magnitude();
+        let valid_accel = (norm - 1.).abs() < Self::ACCEL_TOLERANCE;
+
+        if !self.initialized {
+            if valid_accel {
+                self.quat = Quaternion::from_arc(accel / norm, Vector3::unit_y(), None);
+                self.initialized = true;
+            }
+            return;
+        }
+
+        let mut rotation = gyro.map(f64::to_radians);
+        if valid_accel {
+            // The accelerometer measures the reaction to gravity, which points up.
+            let up = self.quat.conjugate().rotate_vector(Vector3::unit_y());
+            let error = (accel / norm).cross(up);
+            if self.ki > 0. {
+                self.integral += error * self.ki * dt;
+                rotation += self.integral;
+            }
+            rotation += error * self.kp;
+        }
+
+        let delta = Quaternion::from_sv(0., rotation * 0.5 * dt);
+        self.quat = (self.quat + self.quat * delta).normalize();
+    }
+
+    /// Rotation from the controller frame to the world frame.
+    pub fn quaternion(&self) -> Quaternion<f64> {
+        self.quat
+    }
+
+    /// Unit vector pointing to the ground, in the controller frame.
+    pub fn gravity(&self) -> Vector3<f64> {
+        self.quat.conjugate().rotate_vector(-Vector3::unit_y())
+    }
+
+    pub fn reset(&mut self) {
+        *self = Orientation {
+            kp: self.kp,
+            ki: self.ki,
+            ..Orientation::new()
+        };
+    }
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Orientation::new()
+    }
+}
+
+#[cfg(test)]
+fn imu_from_sdl(gyro: Vector3<f64>, accel: Vector3<f64>) -> IMU {
+    IMU {
+        gyro: vec3(-gyro.z, gyro.x, -gyro.y),
+        accel: vec3(accel.z, -accel.x, accel.y),
+    }
+}
+
+#[test]
+fn gravity_from_accel() {
+    let mut orientation = Orientation::new();
+    // Held vertically, the accelerometer pointing backward.
+    let imu = imu_from_sdl(Vector3::zero(), vec3(0., 0., 1.));
+    for _ in 0..100 {
+        orientation.update(&imu, IMU::SAMPLE_DURATION);
+    }
+    let gravity = orientation.gravity();
+    assert!(
+        (gravity - vec3(0., 0., -1.)).magnitude() < 1e-6,
+        "{:?}",
+        gravity
+    );
+}
+
+#[test]
+fn integrate_gyro() {
+    let mut orientation = Orientation::new();
+    orientation.kp = 0.;
+    let flat = imu_from_sdl(Vector3::zero(), Vector3::unit_y());
+    orientation.update(&flat, IMU::SAMPLE_DURATION);
+    // 90° around the vertical axis in one second.
+    let turn = imu_from_sdl(vec3(0., 90., 0.), Vector3::unit_y());
+    for _ in 0..IMU::SAMPLE_PER_SECOND {
+        orientation.update(&turn, IMU::SAMPLE_DURATION);
+    }
+    let forward = orientation.quaternion().rotate_vector(-Vector3::unit_z());
+    assert!(
+        (forward - vec3(-1., 0., 0.)).magnitude() < 1e-3,
+        "{:?}",
+        forward
+    );
+}
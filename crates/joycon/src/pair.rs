@@ -0,0 +1,159 @@
+use crate::{JoyCon, Report};
+use anyhow::{ensure, Context, Result};
+use hidapi::HidApi;
+use joycon_sys::{input::WhichController, light, output::RumbleData};
+use tracing::instrument;
+
+/// How long `JoyConPair::tick` waits on one Joy-Con before polling the other, in milliseconds.
+const POLL_TIMEOUT: i32 = 1;
+
+/// A left and a right Joy-Con used together as one controller.
+///
+/// Each Joy-Con keeps its own calibration and IMU handler, `tick` merges the
+/// latest report of each into one.
+#[derive(Debug)]
+pub struct JoyConPair {
+    pub left: JoyCon,
+    pub right: JoyCon,
+    last_left: Option<Report>,
+    last_right: Option<Report>,
+}
+
+/// The reports of both Joy-Cons after one `JoyConPair::tick`.
+#[derive(Debug, Clone)]
+pub struct PairReport {
+    /// Buttons of both Joy-Cons, the left stick of the left one, the right
+    /// stick and the motion of the right one.
+    ///
+    /// The motion is only there when the right Joy-Con sent a new report.
+    pub merged: Report,
+    /// The report read from the left Joy-Con during this tick, if any.
+    pub left: Option<Report>,
+    /// The report read from the right Joy-Con during this tick, if any.
+    pub right: Option<Report>,
+}
+
+impl JoyConPair {
+    #[instrument(level = "info", err)]
+    pub fn new(left: JoyCon, right: JoyCon) -> Result<JoyConPair> {
+        ensure!(
+            left.device_type() == WhichController::LeftJoyCon,
+            "expected a left Joy-Con, got a {}",
+            left.device_type()
+        );
+        ensure!(
+            right.device_type() == WhichController::RightJoyCon,
+            "expected a right Joy-Con, got a {}",
+            right.device_type()
+        );
+        Ok(JoyConPair {
+            left,
+            right,
+            last_left: None,
+            last_right: None,
+        })
+    }
+
+    /// Opens the given Joy-Cons, for when several of them are connected.
+    pub fn open_devices(
+        api: &HidApi,
+        left: &hidapi::DeviceInfo,
+        right: &hidapi::DeviceInfo,
+    ) -> Result<JoyConPair> {
+        let open = |info: &hidapi::DeviceInfo| -> Result<JoyCon> {
+            let device = info
+                .open_device(api)
+                .with_context(|| format!("error opening the HID device {:?}", info))?;
+            JoyCon::new(device, info.clone())
+        };
+        JoyConPair::new(open(left)?, open(right)?)
+    }
+
+    /// Waits for a report from either Joy-Con and merges it with the latest
+    /// one of the other.
+    ///
+    /// Both are polled in turn, so one of them stalling or dropping packets
+    /// doesn't hold back the other. The first call waits for both.
+    pub fn tick(&mut self) -> Result<PairReport> {
+        loop {
+            if let Some(report) = self.try_tick()? {
+                return Ok(report);
+            }
+        }
+    }
+
+    /// Polls each Joy-Con once, waiting a few milliseconds at most.
+    ///
+    /// Returns `None` if neither sent a new report, or if one of them hasn't
+    /// reported yet.
+    pub fn try_tick(&mut self) -> Result<Option<PairReport>> {
+        let left = self.left.try_tick(POLL_TIMEOUT)?;
+        let right = self.right.try_tick(POLL_TIMEOUT)?;
+        if left.is_some() {
+            self.last_left = left.clone();
+        }
+        if right.is_some() {
+            self.last_right = right.clone();
+        }
+        if left.is_none() && right.is_none() {
+            return Ok(None);
+        }
+        Ok(match (&self.last_left, &self.last_right) {
+            (Some(last_left), Some(last_right)) => Some(PairReport {
+                merged: merge(last_left, last_right, right.is_some()),
+                left,
+                right,
+            }),
+            _ => None,
+        })
+    }
+
+    pub fn enable_imu(&mut self) -> Result<()> {
+        self.left.enable_imu()?;
+        self.right.enable_imu()
+    }
+
+    pub fn load_calibration(&mut self) -> Result<()> {
+        self.left.load_calibration()?;
+        self.right.load_calibration()
+    }
+
+    pub fn enable_vibration(&mut self, enable: bool) -> Result<()> {
+        self.left.enable_vibration(enable)?;
+        self.right.enable_vibration(enable)
+    }
+
+    pub fn set_rumble(&mut self, rumble: RumbleData) -> Result<()> {
+        self.left.set_rumble(rumble)?;
+        self.right.set_rumble(rumble)
+    }
+
+    /// Shows the same lights on both Joy-Cons.
+    pub fn set_player_light(&mut self, player_lights: light::PlayerLights) -> Result<()> {
+        self.left.set_player_light(player_lights)?;
+        self.right.set_player_light(player_lights)
+    }
+
+    /// Only the right Joy-Con has a home button light.
+    pub fn set_home_light(&mut self, home_light: light::HomeLight) -> Result<()> {
+        self.right.set_home_light(home_light)
+    }
+}
+
+/// `fresh` tells whether `right` was just read, its motion is only used once.
+fn merge(left: &Report, right: &Report, fresh: bool) -> Report {
+    // Report the emptier battery so that a low Joy-Con is noticed.
+    let info = if left.info.battery_level() < right.info.battery_level() {
+        left.info
+    } else {
+        right.info
+    };
+    Report {
+        left_stick: left.left_stick,
+        buttons: left.buttons | right.buttons,
+        info,
+        imu: if fresh { right.imu } else { None },
+        gyro_recalibrated: fresh && right.gyro_recalibrated,
+        ..right.clone()
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::{bail, Result};
+use cgmath::{Quaternion, Vector3};
+use hid_gamepad::sys::{GamepadDevice, Report};
+use joycon::{
+    hidapi::{DeviceInfo, HidApi},
+    joycon_sys::{
+        input::{BatteryLevel, WhichController},
+        light,
+        output::RumbleData,
+        JOYCON_L_BT, JOYCON_R_BT, NINTENDO_VENDOR_ID,
+    },
+    Grip, JoyCon, JoyConPair, Orientation,
+};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// 操作に使うコントローラー
+///
+/// hid-gamepad が対応しているコントローラー(Proコン、Joy-Con、DualShock 4)を同じように扱う。
+/// ランプや振動は Joy-Con・Proコンにしかないので、その他のコントローラーでは何もしない。
+pub struct Controller {
+    device: Box<dyn GamepadDevice>,
+    /// 感度を保存する際の名前
+    id: String,
+    /// ジャイロと加速度センサから推定した姿勢
+    ///
+    /// Joy-Con・Proコンは joycon が推定したものをそのまま使い、その他のコントローラーでは自前で推定する。
+    orientation: Orientation,
+    /// 最新の姿勢(コントローラーから見た世界への回転)
+    quaternion: Quaternion<f64>,
+    /// 最新の重力の向き(コントローラーの座標系)
+    gravity: Vector3<f64>,
+    /// 最新のレポートでジャイロのずれを測り直したか
+    gyro_recalibrated: bool,
+}
+
+impl Controller {
+    fn new(device: Box<dyn GamepadDevice>, id: String) -> Self {
+        let orientation = Orientation::new();
+        Controller {
+            device,
+            id,
+            quaternion: orientation.quaternion(),
+            gravity: orientation.gravity(),
+            orientation,
+            gyro_recalibrated: false,
+        }
+    }
+
+    /// 対応しているコントローラーなら開く(`grip` はJoy-Conを1本で使う時の持ち方)
+    pub fn open(api: &HidApi, device_info: &DeviceInfo, grip: Grip) -> Result<Option<Controller>> {
+        let mut device = match hid_gamepad::open_gamepad(api, device_info)? {
+            Some(device) => device,
+            None => return Ok(None),
+        };
+        // Joy-Con・ProコンはMACアドレス、それ以外はシリアル番号(なければデバイスのパス)
+        let id = match device.as_any().downcast_mut::<JoyCon>() {
+            Some(joycon) => {
+                joycon.set_grip(grip);
+                joycon.get_dev_info()?.mac_address.to_string()
+            }
+            None => match device_info.serial_number() {
+                Some(serial) if !serial.is_empty() => serial.to_string(),
+                _ => device_info.path().to_string_lossy().into_owned(),
+            },
+        };
+        Ok(Some(Controller::new(device, id)))
+    }
+
+    /// 左右のJoy-Conを1台のコントローラーとして使う
+    pub fn pair(mut pair: JoyConPair) -> Result<Controller> {
+        pair.enable_imu()?;
+        pair.load_calibration()?;
+        let id = format!(
+            "{}+{}",
+            pair.left.get_dev_info()?.mac_address,
+            pair.right.get_dev_info()?.mac_address
+        );
+        Ok(Controller::new(Box::new(pair), id))
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// 次のレポートを待ち、姿勢を更新する
+    ///
+    /// `timeout` 待っても届かなければ `None`(Joy-Con・Proコン以外は届くまで待つ)。
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Report>> {
+        let deadline = Instant::now() + timeout;
+        let device = self.device.as_any();
+        let joycon = if let Some(pair) = device.downcast_mut::<JoyConPair>() {
+            loop {
+                if let Some(report) = pair.try_tick()? {
+                    break Some(report.merged);
+                }
+                if Instant::now() >= deadline {
+                    return Ok(None);
+                }
+            }
+        } else if let Some(joycon) = device.downcast_mut::<JoyCon>() {
+            match joycon.try_tick(timeout.as_millis() as i32)? {
+                Some(report) => Some(report),
+                None => return Ok(None),
+            }
+        } else {
+            None
+        };
+        if let Some(report) = joycon {
+            self.quaternion = report.orientation;
+            self.gravity = report.gravity;
+            self.gyro_recalibrated = report.gyro_recalibrated;
+            return Ok(Some(report.into()));
+        }
+
+        let report = self.device.recv()?;
+        let dt = 1.0 / report.frequency as f64;
+        for motion in &report.motion {
+            self.orientation.update_motion(motion, dt);
+        }
+        self.quaternion = self.orientation.quaternion();
+        self.gravity = self.orientation.gravity();
+        Ok(Some(report))
+    }
+
+    /// コントローラーから見た世界への回転
+    pub fn orientation(&self) -> Quaternion<f64> {
+        self.quaternion
+    }
+
+    /// 重力の向き(コントローラーの座標系の単位ベクトル)
+    pub fn gravity(&self) -> Vector3<f64> {
+        self.gravity
+    }
+
+    /// 最新のレポートでジャイロのずれを測り直したか(Joy-Con・Proコンのみ)
+    pub fn gyro_recalibrated(&self) -> bool {
+        self.gyro_recalibrated
+    }
+
+    /// ランプや振動を操作できる Joy-Con・Proコン
+    fn joycons(&mut self) -> Vec<&mut JoyCon> {
+        let device = self.device.as_any();
+        if device.is::<JoyConPair>() {
+            let pair = device.downcast_mut::<JoyConPair>().unwrap();
+            vec![&mut pair.left, &mut pair.right]
+        } else {
+            device.downcast_mut::<JoyCon>().into_iter().collect()
+        }
+    }
+
+    /// 電池残量(左右のJoy-Conなら少ない方、わからなければ `None`)
+    pub fn battery_level(&mut self) -> Result<Option<BatteryLevel>> {
+        let mut level = None;
+        for joycon in self.joycons() {
+            let battery = joycon.tick()?.info.battery_level();
+            level = Some(level.map_or(battery, |level: BatteryLevel| level.min(battery)));
+        }
+        Ok(level)
+    }
+
+    pub fn enable_vibration(&mut self, enable: bool) -> Result<()> {
+        for joycon in self.joycons() {
+            joycon.enable_vibration(enable)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_rumble(&mut self, rumble: RumbleData) -> Result<()> {
+        for joycon in self.joycons() {
+            joycon.set_rumble(rumble)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_player_light(&mut self, player_lights: light::PlayerLights) -> Result<()> {
+        for joycon in self.joycons() {
+            joycon.set_player_light(player_lights)?;
+        }
+        Ok(())
+    }
+
+    /// ホームボタンのランプ(L側のJoy-Conにはない)
+    pub fn set_home_light(&mut self, home_light: light::HomeLight) -> Result<()> {
+        for joycon in self.joycons() {
+            if joycon.device_type() != WhichController::LeftJoyCon {
+                joycon.set_home_light(home_light)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 1台のコントローラーとして使う左右のJoy-Con(`左のMACアドレス+右のMACアドレス`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PairSpec {
+    left: String,
+    right: String,
+}
+
+impl PairSpec {
+    /// 空いているデバイスから、左右のJoy-Conを両方探す
+    pub fn find<'a>(&self, free: &[&'a DeviceInfo]) -> Option<(&'a DeviceInfo, &'a DeviceInfo)> {
+        let find = |product_id, address: &str| {
+            free.iter().copied().find(|info| {
+                info.vendor_id() == NINTENDO_VENDOR_ID
+                    && info.product_id() == product_id
+                    && info.serial_number().map(mac_address).as_deref() == Some(address)
+            })
+        };
+        Some((
+            find(JOYCON_L_BT, &self.left)?,
+            find(JOYCON_R_BT, &self.right)?,
+        ))
+    }
+
+    /// 左右どちらかのJoy-Conか
+    pub fn contains(&self, info: &DeviceInfo) -> bool {
+        info.vendor_id() == NINTENDO_VENDOR_ID
+            && info
+                .serial_number()
+                .map(mac_address)
+                .is_some_and(|address| address == self.left || address == self.right)
+    }
+}
+
+impl FromStr for PairSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some((left, right)) = s.split_once('+') {
+            let (left, right) = (mac_address(left), mac_address(right));
+            if left.len() == 12 && right.len() == 12 {
+                return Ok(PairSpec { left, right });
+            }
+        }
+        bail!(
+            "invalid Joy-Con pair `{}` (expected two MAC addresses like `98:b6:e9:00:00:01+98:b6:e9:00:00:02`)",
+            s
+        )
+    }
+}
+
+/// MACアドレスの16進数の部分だけを小文字で(OSによって区切り文字が違うため)
+fn mac_address(s: &str) -> String {
+    s.chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[test]
+fn parse_pair() {
+    let pair: PairSpec = "98:B6:E9:00:00:01+98-b6-e9-00-00-02".parse().unwrap();
+    assert_eq!(pair.left, "98b6e9000001");
+    assert_eq!(pair.right, "98b6e9000002");
+    assert!("98:b6:e9:00:00:01".parse::<PairSpec>().is_err());
+    assert!("98:b6:e9:00:00:01+98:b6".parse::<PairSpec>().is_err());
+}
@@ -0,0 +1,670 @@
+use crate::profile::Action;
+use anyhow::{bail, Result};
+use hid_gamepad_types::JoyKey;
+use serde::{de, Deserialize, Deserializer};
+use std::{fmt, time::Duration};
+
+/// ボタンへの割り当て
+#[derive(Debug, Clone, PartialEq)]
+pub enum Binding {
+    /// 押している間、動作を続ける(遅延なし)
+    Press(Action),
+    /// 押し方によって動作を使い分ける
+    Gestures {
+        /// 短く押して離した時(押下と解放を続けて送る)
+        tap: Action,
+        /// `hold_time` 以上押し続けた時(離すまで続ける)
+        hold: Action,
+        /// `double_tap_time` 以内にもう一度押した時(離すまで続ける)
+        double: Action,
+    },
+    /// 押した時に一回実行し、押し続けると `delay` 後から `interval` ごとに繰り返す
+    Repeat {
+        action: Action,
+        delay: Duration,
+        interval: Duration,
+    },
+}
+
+impl Binding {
+    /// 割り当てられた全ての動作
+    pub fn actions(&self) -> Vec<&Action> {
+        match self {
+            Binding::Press(action) => vec![action],
+            Binding::Gestures { tap, hold, double } => vec![tap, hold, double],
+            Binding::Repeat { action, .. } => vec![action],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GestureTable {
+    tap: Option<Action>,
+    hold: Option<Action>,
+    double: Option<Action>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RepeatTable {
+    action: Action,
+    /// 繰り返しの間隔(s)
+    repeat: f64,
+    /// 繰り返しを始めるまでの時間(s、省略すると `repeat` と同じ)
+    delay: Option<f64>,
+}
+
+impl<'de> Deserialize<'de> for Binding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // 文字列・配列なら押している間の動作、テーブルなら押し方ごとの動作か繰り返し
+        let value = toml::Value::deserialize(deserializer)?;
+        let table = match &value {
+            toml::Value::Table(table) => table,
+            _ => {
+                return Action::deserialize(value)
+                    .map(Binding::Press)
+                    .map_err(de::Error::custom)
+            }
+        };
+        if table.contains_key("repeat") {
+            let RepeatTable {
+                action,
+                repeat,
+                delay,
+            } = RepeatTable::deserialize(value).map_err(de::Error::custom)?;
+            let delay = delay.unwrap_or(repeat);
+            if !(repeat > 0.0 && repeat.is_finite() && delay >= 0.0 && delay.is_finite()) {
+                return Err(de::Error::custom(
+                    "`repeat` must be positive and `delay` must not be negative",
+                ));
+            }
+            return Ok(Binding::Repeat {
+                action,
+                delay: Duration::from_secs_f64(delay),
+                interval: Duration::from_secs_f64(repeat),
+            });
+        }
+        let GestureTable { tap, hold, double } =
+            GestureTable::deserialize(value).map_err(de::Error::custom)?;
+        if tap.is_none() && hold.is_none() && double.is_none() {
+            return Err(de::Error::custom(
+                "expected at least one of `tap`, `hold` or `double`",
+            ));
+        }
+        Ok(Binding::Gestures {
+            tap: tap.unwrap_or(Action::None),
+            hold: hold.unwrap_or(Action::None),
+            double: double.unwrap_or(Action::None),
+        })
+    }
+}
+
+/// 同時押し(`"l+r"` のように書く)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chord {
+    pub buttons: Vec<JoyKey>,
+    pub action: Action,
+}
+
+impl Chord {
+    pub fn parse(buttons: &str, action: Action) -> Result<Chord> {
+        let mut parsed = vec![];
+        for name in buttons.split('+') {
+            let button: JoyKey = name.trim().parse().map_err(anyhow::Error::msg)?;
+            if parsed.contains(&button) {
+                bail!(
+                    "button `{}` appears twice in the chord `{}`",
+                    button,
+                    buttons
+                );
+            }
+            parsed.push(button);
+        }
+        if parsed.len() < 2 {
+            bail!("a chord needs at least two buttons (got `{}`)", buttons);
+        }
+        Ok(Chord {
+            buttons: parsed,
+            action,
+        })
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = self.buttons.iter().map(|b| b.name()).collect();
+        f.write_str(&names.join("+"))
+    }
+}
+
+/// プロファイルの `[gestures]` の設定(秒)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GestureSettings {
+    /// これ以上押し続けると長押しになる
+    pub hold_time: f64,
+    /// 離してからこの時間内に押すとダブルタップになる
+    pub double_tap_time: f64,
+    /// 同時押しとみなす押下の時間差
+    pub chord_time: f64,
+}
+
+impl GestureSettings {
+    pub fn validate(&self) -> Result<()> {
+        for (name, value) in [
+            ("hold_time", self.hold_time),
+            ("double_tap_time", self.double_tap_time),
+            ("chord_time", self.chord_time),
+        ] {
+            if !(value >= 0.0 && value.is_finite()) {
+                bail!("{} must not be negative (got {})", name, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for GestureSettings {
+    fn default() -> Self {
+        GestureSettings {
+            hold_time: 0.3,
+            double_tap_time: 0.25,
+            chord_time: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+enum State {
+    #[default]
+    Released,
+    /// 同時押しの一部かどうか待っている
+    ChordWait { since: Duration, binding: Binding },
+    /// 押した時の動作を続けている(離す時はレイヤーが変わっていても同じ動作を使う)
+    Active(Action),
+    /// 長押しになるか待っている
+    Down {
+        since: Duration,
+        tap: Action,
+        hold: Action,
+        double: Action,
+    },
+    /// 2回目の押下を待っている
+    WaitDouble {
+        since: Duration,
+        tap: Action,
+        double: Action,
+    },
+    /// 押し続けている間、`next` から繰り返す
+    Repeating {
+        next: Duration,
+        action: Action,
+        interval: Duration,
+    },
+    /// 同時押しとして使ったので、離すまで何もしない
+    Consumed,
+}
+
+/// ボタンの押し方(タップ・長押し・ダブルタップ・同時押し)を判定する
+///
+/// 時刻は呼び出し側が渡すので、実際の時間を待たずに試せる。
+/// ジェスチャーを割り当てていないボタンは、同時押しに含まれなければ遅延なく動作する。
+#[derive(Debug, Default)]
+pub struct GestureDetector {
+    states: [State; JoyKey::ALL.len()],
+    /// 実行中の同時押し(ボタン、動作)
+    chords: Vec<(Vec<JoyKey>, Action)>,
+}
+
+impl GestureDetector {
+    pub fn new() -> Self {
+        GestureDetector::default()
+    }
+
+    /// 時刻 `now` のボタンの状態を反映し、出力すべき動作を(動作, 押下か)の順に返す
+    ///
+    /// `binding` は押した時点の割り当てを求める。
+    pub fn update(
+        &mut self,
+        now: Duration,
+        settings: &GestureSettings,
+        chords: &[Chord],
+        is_pressed: impl Fn(JoyKey) -> bool,
+        binding: impl Fn(JoyKey) -> Binding,
+    ) -> Vec<(Action, bool)> {
+        let hold_time = Duration::from_secs_f64(settings.hold_time);
+        let double_tap_time = Duration::from_secs_f64(settings.double_tap_time);
+        let chord_time = Duration::from_secs_f64(settings.chord_time);
+        let mut out = vec![];
+
+        // 新たに押したボタン
+        for (i, button) in JoyKey::ALL.iter().enumerate() {
+            if !is_pressed(*button) {
+                continue;
+            }
+            match &self.states[i] {
+                State::Released => {
+                    let binding = binding(*button);
+                    self.states[i] = if chords.iter().any(|c| c.buttons.contains(button)) {
+                        State::ChordWait {
+                            since: now,
+                            binding,
+                        }
+                    } else {
+                        start(binding, now, &mut out)
+                    };
+                }
+                State::WaitDouble { double, .. } => {
+                    let double = double.clone();
+                    emit(&mut out, &double, true);
+                    self.states[i] = State::Active(double);
+                }
+                _ => {}
+            }
+        }
+
+        // 同時押しの成立(それを含むより長い同時押しがまだ成立しうる間は待つ)
+        for chord in chords {
+            let complete = chord.buttons.iter().all(|button| {
+                let i = index(*button);
+                is_pressed(*button) && matches!(self.states[i], State::ChordWait { .. })
+            });
+            let longer_pending = || {
+                chords.iter().any(|longer| {
+                    longer.buttons.len() > chord.buttons.len()
+                        && chord.buttons.iter().all(|b| longer.buttons.contains(b))
+                        && self.can_complete(longer, now, chord_time, &is_pressed)
+                })
+            };
+            if complete && !longer_pending() {
+                for button in &chord.buttons {
+                    self.states[index(*button)] = State::Consumed;
+                }
+                emit(&mut out, &chord.action, true);
+                self.chords
+                    .push((chord.buttons.clone(), chord.action.clone()));
+            }
+        }
+
+        // 同時押しの解除(どれか一つを離したら終わる)
+        let (released, active): (Vec<_>, Vec<_>) = self
+            .chords
+            .drain(..)
+            .partition(|(buttons, _)| buttons.iter().any(|b| !is_pressed(*b)));
+        self.chords = active;
+        for (_, action) in released {
+            emit(&mut out, &action, false);
+        }
+
+        // 時間経過と解放
+        for (i, button) in JoyKey::ALL.iter().enumerate() {
+            let pressed = is_pressed(*button);
+            let state = std::mem::take(&mut self.states[i]);
+            self.states[i] = match state {
+                State::ChordWait { since, binding } if !pressed || now - since >= chord_time => {
+                    // 同時押しにならなかったので、押した時刻から普通に扱う
+                    let state = start(binding, since, &mut out);
+                    if pressed {
+                        advance(state, now, hold_time, &mut out)
+                    } else {
+                        release(state, now, &mut out)
+                    }
+                }
+                state if pressed => advance(state, now, hold_time, &mut out),
+                State::WaitDouble { since, tap, .. } if now - since >= double_tap_time => {
+                    emit(&mut out, &tap, true);
+                    emit(&mut out, &tap, false);
+                    State::Released
+                }
+                state @ State::WaitDouble { .. } => state,
+                State::Consumed => State::Released,
+                state => release(state, now, &mut out),
+            };
+        }
+        out
+    }
+
+    /// 同時押しが成立しているか、`chord_time` 以内に成立しうるか
+    ///
+    /// 含まれるボタンが全て、押して待っているか離したままで、最初の押下から `chord_time` 以内なら成立しうる。
+    fn can_complete(
+        &self,
+        chord: &Chord,
+        now: Duration,
+        chord_time: Duration,
+        is_pressed: impl Fn(JoyKey) -> bool,
+    ) -> bool {
+        let mut first: Option<Duration> = None;
+        let mut complete = true;
+        for button in &chord.buttons {
+            match (&self.states[index(*button)], is_pressed(*button)) {
+                (State::ChordWait { since, .. }, true) => {
+                    first = Some(first.map_or(*since, |first| first.min(*since)));
+                }
+                (State::Released, false) => complete = false,
+                _ => return false,
+            }
+        }
+        first.is_some_and(|first| complete || now - first < chord_time)
+    }
+}
+
+/// 押し続けている間の処理
+fn advance(
+    state: State,
+    now: Duration,
+    hold_time: Duration,
+    out: &mut Vec<(Action, bool)>,
+) -> State {
+    match state {
+        State::Down {
+            since,
+            tap,
+            hold,
+            double,
+        } => {
+            if hold != Action::None && now - since >= hold_time {
+                emit(out, &hold, true);
+                State::Active(hold)
+            } else {
+                State::Down {
+                    since,
+                    tap,
+                    hold,
+                    double,
+                }
+            }
+        }
+        State::Repeating {
+            next,
+            action,
+            interval,
+        } if now >= next => {
+            emit(out, &action, true);
+            emit(out, &action, false);
+            // 遅れた分をまとめて送らない
+            State::Repeating {
+                next: (next + interval).max(now),
+                action,
+                interval,
+            }
+        }
+        state => state,
+    }
+}
+
+fn index(button: JoyKey) -> usize {
+    JoyKey::ALL.iter().position(|b| *b == button).unwrap()
+}
+
+fn emit(out: &mut Vec<(Action, bool)>, action: &Action, pressed: bool) {
+    if *action != Action::None {
+        out.push((action.clone(), pressed));
+    }
+}
+
+/// 押した時の状態
+fn start(binding: Binding, now: Duration, out: &mut Vec<(Action, bool)>) -> State {
+    match binding {
+        Binding::Press(action) => {
+            emit(out, &action, true);
+            State::Active(action)
+        }
+        Binding::Gestures { tap, hold, double } => State::Down {
+            since: now,
+            tap,
+            hold,
+            double,
+        },
+        Binding::Repeat {
+            action,
+            delay,
+            interval,
+        } => {
+            emit(out, &action, true);
+            emit(out, &action, false);
+            State::Repeating {
+                next: now + delay,
+                action,
+                interval,
+            }
+        }
+    }
+}
+
+/// 離した時の状態
+fn release(state: State, now: Duration, out: &mut Vec<(Action, bool)>) -> State {
+    match state {
+        State::Active(action) => {
+            emit(out, &action, false);
+            State::Released
+        }
+        // 長押しにならずに離した
+        State::Down { tap, double, .. } if double != Action::None => State::WaitDouble {
+            since: now,
+            tap,
+            double,
+        },
+        State::Down { tap, .. } => {
+            emit(out, &tap, true);
+            emit(out, &tap, false);
+            State::Released
+        }
+        State::Repeating { .. } => State::Released,
+        state => state,
+    }
+}
+
+#[cfg(test)]
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn tap_and_hold() {
+    use enigo::MouseButton;
+
+    let settings = GestureSettings::default();
+    let binding = |_: JoyKey| Binding::Gestures {
+        tap: Action::Click(MouseButton::Right),
+        hold: Action::Mouse(MouseButton::Right),
+        double: Action::None,
+    };
+    let r = |pressed: bool| move |b: JoyKey| pressed && b == JoyKey::R;
+    let mut detector = GestureDetector::new();
+
+    // 短く押して離すとクリック
+    assert!(detector
+        .update(ms(0), &settings, &[], r(true), binding)
+        .is_empty());
+    assert!(detector
+        .update(ms(100), &settings, &[], r(true), binding)
+        .is_empty());
+    assert_eq!(
+        detector.update(ms(150), &settings, &[], r(false), binding),
+        vec![
+            (Action::Click(MouseButton::Right), true),
+            (Action::Click(MouseButton::Right), false)
+        ]
+    );
+
+    // 長押しでドラッグ
+    assert!(detector
+        .update(ms(1000), &settings, &[], r(true), binding)
+        .is_empty());
+    assert_eq!(
+        detector.update(ms(1300), &settings, &[], r(true), binding),
+        vec![(Action::Mouse(MouseButton::Right), true)]
+    );
+    assert!(detector
+        .update(ms(2000), &settings, &[], r(true), binding)
+        .is_empty());
+    assert_eq!(
+        detector.update(ms(2100), &settings, &[], r(false), binding),
+        vec![(Action::Mouse(MouseButton::Right), false)]
+    );
+}
+
+#[test]
+fn double_tap() {
+    let settings = GestureSettings::default();
+    let tap = Action::Key(vec![enigo::Key::Layout('a')]);
+    let double = Action::Key(vec![enigo::Key::Layout('b')]);
+    let binding = |_: JoyKey| Binding::Gestures {
+        tap: tap.clone(),
+        hold: Action::None,
+        double: double.clone(),
+    };
+    let a = |pressed: bool| move |b: JoyKey| pressed && b == JoyKey::E;
+    let mut detector = GestureDetector::new();
+
+    // 2回目を待ってからタップとして送る
+    detector.update(ms(0), &settings, &[], a(true), binding);
+    assert!(detector
+        .update(ms(50), &settings, &[], a(false), binding)
+        .is_empty());
+    assert!(detector
+        .update(ms(200), &settings, &[], a(false), binding)
+        .is_empty());
+    assert_eq!(
+        detector.update(ms(300), &settings, &[], a(false), binding),
+        vec![(tap.clone(), true), (tap.clone(), false)]
+    );
+
+    detector.update(ms(1000), &settings, &[], a(true), binding);
+    detector.update(ms(1050), &settings, &[], a(false), binding);
+    assert_eq!(
+        detector.update(ms(1200), &settings, &[], a(true), binding),
+        vec![(double.clone(), true)]
+    );
+    assert_eq!(
+        detector.update(ms(1250), &settings, &[], a(false), binding),
+        vec![(double.clone(), false)]
+    );
+}
+
+#[test]
+fn chords() {
+    use enigo::Key;
+
+    let settings = GestureSettings::default();
+    let undo = Action::Key(vec![Key::Control, Key::Layout('z')]);
+    let chords = [Chord::parse("l+r", undo.clone()).unwrap()];
+    let key = |c| Action::Key(vec![Key::Layout(c)]);
+    let binding = |b: JoyKey| match b {
+        JoyKey::E => Binding::Press(key('a')),
+        _ => Binding::Press(key('l')),
+    };
+    let mut detector = GestureDetector::new();
+    let mut update = |now, pressed: &[JoyKey]| {
+        detector.update(
+            ms(now),
+            &settings,
+            &chords,
+            |b| pressed.contains(&b),
+            binding,
+        )
+    };
+
+    // 同時押しに含まれないボタンはすぐに反応する
+    assert_eq!(update(0, &[JoyKey::E, JoyKey::L]), vec![(key('a'), true)]);
+    assert_eq!(
+        update(20, &[JoyKey::E, JoyKey::L, JoyKey::R]),
+        vec![(undo.clone(), true)]
+    );
+    // どれか一つを離すと終わり、残りは離すまで何もしない
+    assert_eq!(
+        update(100, &[JoyKey::R]),
+        vec![(undo, false), (key('a'), false)]
+    );
+    assert!(update(150, &[]).is_empty());
+
+    // 同時押しにならなければ、待った後で単独の動作になる
+    assert!(update(200, &[JoyKey::L]).is_empty());
+    assert_eq!(update(260, &[JoyKey::L]), vec![(key('l'), true)]);
+    assert_eq!(update(300, &[]), vec![(key('l'), false)]);
+    update(400, &[JoyKey::L]);
+    assert_eq!(update(420, &[]), vec![(key('l'), true), (key('l'), false)]);
+}
+
+#[test]
+fn overlapping_chords() {
+    use enigo::Key;
+
+    let settings = GestureSettings::default();
+    let undo = Action::Key(vec![Key::Control, Key::Layout('z')]);
+    let redo = Action::Key(vec![Key::Control, Key::Layout('y')]);
+    let chords = [
+        Chord::parse("l+r", undo.clone()).unwrap(),
+        Chord::parse("l+r+zl", redo.clone()).unwrap(),
+    ];
+    let binding = |_: JoyKey| Binding::Press(Action::None);
+    let mut detector = GestureDetector::new();
+    let mut update = |now, pressed: &[JoyKey]| {
+        detector.update(
+            ms(now),
+            &settings,
+            &chords,
+            |b| pressed.contains(&b),
+            binding,
+        )
+    };
+
+    // 短い方がそろっても、長い方がそろうまで待つ
+    assert!(update(0, &[JoyKey::L]).is_empty());
+    assert!(update(10, &[JoyKey::L, JoyKey::R]).is_empty());
+    assert_eq!(
+        update(20, &[JoyKey::L, JoyKey::R, JoyKey::ZL]),
+        vec![(redo.clone(), true)]
+    );
+    assert_eq!(update(100, &[]), vec![(redo, false)]);
+
+    // 長い方が `chord_time` 以内にそろわなければ、短い方になる
+    assert!(update(200, &[JoyKey::L]).is_empty());
+    assert!(update(210, &[JoyKey::L, JoyKey::R]).is_empty());
+    assert_eq!(
+        update(250, &[JoyKey::L, JoyKey::R]),
+        vec![(undo.clone(), true)]
+    );
+    assert_eq!(update(300, &[]), vec![(undo, false)]);
+}
+
+#[test]
+fn repeat_while_held() {
+    let settings = GestureSettings::default();
+    let tab = Action::Key(vec![enigo::Key::Control, enigo::Key::Tab]);
+    let binding = |_: JoyKey| Binding::Repeat {
+        action: tab.clone(),
+        delay: ms(400),
+        interval: ms(100),
+    };
+    let right = |pressed: bool| move |b: JoyKey| pressed && b == JoyKey::Right;
+    let mut detector = GestureDetector::new();
+    let tap = vec![(tab.clone(), true), (tab.clone(), false)];
+
+    assert_eq!(
+        detector.update(ms(0), &settings, &[], right(true), binding),
+        tap
+    );
+    assert!(detector
+        .update(ms(300), &settings, &[], right(true), binding)
+        .is_empty());
+    assert_eq!(
+        detector.update(ms(400), &settings, &[], right(true), binding),
+        tap
+    );
+    assert!(detector
+        .update(ms(450), &settings, &[], right(true), binding)
+        .is_empty());
+    assert_eq!(
+        detector.update(ms(510), &settings, &[], right(true), binding),
+        tap
+    );
+    assert!(detector
+        .update(ms(550), &settings, &[], right(false), binding)
+        .is_empty());
+    assert!(detector
+        .update(ms(700), &settings, &[], right(false), binding)
+        .is_empty());
+}
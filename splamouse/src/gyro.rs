@@ -0,0 +1,332 @@
+use anyhow::{bail, Result};
+use cgmath::{vec2, InnerSpace, Vector2, Vector3};
+use serde::{de, Deserialize, Deserializer};
+use std::{collections::VecDeque, fmt, str::FromStr};
+
+/// ジャイロの回転をカーソル移動に変換する座標系
+///
+/// JoyShockMapper の GYRO_SPACE と同じ考え方。
+/// <http://gyrowiki.jibbsmart.com/blog:player-space-gyro-and-alternatives-explained>
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum GyroSpace {
+    /// コントローラーの軸そのまま(縦持ち・横持ちで挙動が変わる)
+    Local,
+    /// 重力の周りの回転を左右、水平な軸の周りの回転を上下に使う
+    World,
+    /// ヨーとロールを混ぜて左右に使う(どの持ち方でも自然に動く)
+    #[default]
+    Player,
+}
+
+impl GyroSpace {
+    /// Player space でロールをヨーとして扱う割合の上限
+    const PLAYER_YAW_RELAX: f64 = 1.41;
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GyroSpace::Local => "local",
+            GyroSpace::World => "world",
+            GyroSpace::Player => "player",
+        }
+    }
+
+    /// ジャイロ(deg/s)と重力の向きから、カーソルを動かす角速度(右・下が正)を求める
+    ///
+    /// どちらもSDLの軸(コントローラーを平らに置いた時に+Yが上)で渡す。
+    pub fn transform(self, gyro: Vector3<f64>, gravity: Vector3<f64>) -> Vector2<f64> {
+        let up = -gravity.normalize();
+        let (yaw, pitch) = match self {
+            GyroSpace::Local => (gyro.y, gyro.x),
+            GyroSpace::World => {
+                // コントローラーの左右の軸を水平面に射影したものをピッチの軸にする
+                let pitch_axis = Vector3::unit_x() - up * up.x;
+                let pitch = if pitch_axis.magnitude2() > 1e-6 {
+                    gyro.dot(pitch_axis.normalize())
+                } else {
+                    0.0
+                };
+                (gyro.dot(up), pitch)
+            }
+            GyroSpace::Player => {
+                // 前後の軸の周りの回転(ロール)もヨーとして使うが、合計は超えない
+                let world_yaw = gyro.y * up.y + gyro.z * up.z;
+                let yaw = (world_yaw.abs() * Self::PLAYER_YAW_RELAX)
+                    .min(gyro.y.hypot(gyro.z))
+                    .copysign(world_yaw);
+                (yaw, gyro.x)
+            }
+        };
+        // 右回り(上から見て時計回り)が負の回転なので符号を反転する
+        vec2(-yaw, -pitch)
+    }
+}
+
+impl fmt::Display for GyroSpace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for GyroSpace {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "local" => Ok(GyroSpace::Local),
+            "world" => Ok(GyroSpace::World),
+            "player" => Ok(GyroSpace::Player),
+            _ => bail!(
+                "unknown gyro space `{}` (expected `local`, `world` or `player`)",
+                s
+            ),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for GyroSpace {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// プロファイルの `[gyro]` の設定
+///
+/// 感度は角速度によって `min_sens` から `max_sens` の間で変わる。
+/// ゆっくり動かすと細かく狙え、素早く振ると画面の端まで届く。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GyroSettings {
+    pub space: GyroSpace,
+    /// `min_threshold` 以下の角速度での感度(px/deg)
+    pub min_sens: f64,
+    /// `max_threshold` 以上の角速度での感度(px/deg)
+    pub max_sens: f64,
+    /// 角速度(deg/s)
+    pub min_threshold: f64,
+    /// 角速度(deg/s)
+    pub max_threshold: f64,
+    /// この角速度(deg/s)以下の入力を連続的に弱める(ドリフト防止)。0で無効。
+    pub deadzone: f64,
+    /// この角速度(deg/s)以下の入力を平均して手ぶれを抑える。0で無効。
+    pub smooth_threshold: f64,
+    /// 平均する期間(s)
+    pub smooth_time: f64,
+}
+
+impl GyroSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.min_sens < 0.0 || self.max_sens < 0.0 {
+            bail!("gyro sensitivity must not be negative");
+        }
+        if self.deadzone < 0.0 || self.smooth_threshold < 0.0 || self.smooth_time < 0.0 {
+            bail!("gyro deadzone and smoothing must not be negative");
+        }
+        if self.min_threshold < 0.0 || self.min_threshold > self.max_threshold {
+            bail!(
+                "gyro thresholds must satisfy 0 <= min_threshold <= max_threshold (got {} and {})",
+                self.min_threshold,
+                self.max_threshold
+            );
+        }
+        Ok(())
+    }
+
+    /// 角速度(deg/s)に対する感度(px/deg)
+    pub fn sensitivity(&self, speed: f64) -> f64 {
+        let range = self.max_threshold - self.min_threshold;
+        let t = if range > 0.0 {
+            ((speed - self.min_threshold) / range).clamp(0.0, 1.0)
+        } else if speed < self.min_threshold {
+            0.0
+        } else {
+            1.0
+        };
+        self.min_sens + (self.max_sens - self.min_sens) * t
+    }
+
+    /// カーソル方向の角速度(deg/s)をカーソルの速度(px/s)に変換する
+    pub fn velocity(&self, motion: Vector2<f64>) -> Vector2<f64> {
+        motion * self.sensitivity(motion.magnitude())
+    }
+}
+
+impl Default for GyroSettings {
+    fn default() -> Self {
+        GyroSettings {
+            space: GyroSpace::default(),
+            min_sens: 25.0,
+            max_sens: 50.0,
+            min_threshold: 0.0,
+            max_threshold: 75.0,
+            deadzone: 2.0,
+            smooth_threshold: 5.0,
+            smooth_time: 0.125,
+        }
+    }
+}
+
+/// ソフトデッドゾーンと段階的なスムージング
+///
+/// 遅い入力は直近のサンプルの平均、速い入力はそのまま使い、その間は混ぜる。
+/// 閾値で入力を切り捨てないので、ゆっくり動かしても引っかからない。
+/// <http://gyrowiki.jibbsmart.com/blog:good-gyro-controls-part-1:the-gyro-is-a-mouse>
+#[derive(Debug, Default)]
+pub struct GyroFilter {
+    samples: VecDeque<Vector2<f64>>,
+}
+
+impl GyroFilter {
+    pub fn new() -> Self {
+        GyroFilter::default()
+    }
+
+    /// `dt` 秒間のサンプル(deg/s)を処理する
+    pub fn apply(
+        &mut self,
+        settings: &GyroSettings,
+        motion: Vector2<f64>,
+        dt: f64,
+    ) -> Vector2<f64> {
+        let magnitude = motion.magnitude();
+
+        // ソフトデッドゾーン: 閾値以下では大きさに比例して弱める
+        let motion = if magnitude < settings.deadzone {
+            motion * (magnitude / settings.deadzone)
+        } else {
+            motion
+        };
+
+        // 閾値の半分以下は全て平均、閾値以上はそのまま
+        let upper = settings.smooth_threshold;
+        let lower = upper / 2.0;
+        let magnitude = motion.magnitude();
+        let direct = if upper > lower {
+            ((magnitude - lower) / (upper - lower)).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+
+        let size = ((settings.smooth_time / dt).round() as usize).max(1);
+        self.samples.push_back(motion * (1.0 - direct));
+        while self.samples.len() > size {
+            self.samples.pop_front();
+        }
+        let smoothed = self.samples.iter().sum::<Vector2<f64>>() / self.samples.len() as f64;
+
+        motion * direct + smoothed
+    }
+}
+
+#[cfg(test)]
+fn assert_near(actual: Vector2<f64>, expected: Vector2<f64>) {
+    assert!((actual - expected).magnitude() < 1e-9, "{:?}", actual);
+}
+
+#[test]
+fn flat_controller() {
+    use cgmath::vec3;
+
+    // 平らに持っている時はどの座標系でも同じ
+    let gravity = vec3(0.0, -1.0, 0.0);
+    let gyro = vec3(10.0, -20.0, 0.0);
+    for space in [GyroSpace::Local, GyroSpace::World, GyroSpace::Player] {
+        assert_near(space.transform(gyro, gravity), vec2(20.0, -10.0));
+    }
+}
+
+#[test]
+fn upright_controller() {
+    use cgmath::vec3;
+
+    // 先端を上に向けて立てて持つと、右に振る回転はコントローラーのZ軸の正の回転になる
+    let gravity = vec3(0.0, 0.0, 1.0);
+    let gyro = vec3(0.0, 0.0, 30.0);
+    assert_near(GyroSpace::Local.transform(gyro, gravity), vec2(0.0, 0.0));
+    assert_near(GyroSpace::World.transform(gyro, gravity), vec2(30.0, 0.0));
+    assert_near(GyroSpace::Player.transform(gyro, gravity), vec2(30.0, 0.0));
+
+    // 45°傾けて(ロール)持つと、ワールドではX軸の回転がヨーとピッチに分かれる
+    let gravity = vec3(1.0, -1.0, 0.0).normalize();
+    let gyro = vec3(10.0, 0.0, 0.0);
+    let half = 10.0 * 0.5f64.sqrt();
+    assert_near(GyroSpace::World.transform(gyro, gravity), vec2(half, -half));
+}
+
+#[test]
+fn sensitivity_curve() {
+    let settings = GyroSettings {
+        min_sens: 10.0,
+        max_sens: 40.0,
+        min_threshold: 20.0,
+        max_threshold: 80.0,
+        ..GyroSettings::default()
+    };
+    assert_eq!(settings.sensitivity(0.0), 10.0);
+    assert_eq!(settings.sensitivity(20.0), 10.0);
+    assert_eq!(settings.sensitivity(50.0), 25.0);
+    assert_eq!(settings.sensitivity(200.0), 40.0);
+    assert_near(settings.velocity(vec2(0.0, -5.0)), vec2(0.0, -50.0));
+
+    let step = GyroSettings {
+        min_threshold: 30.0,
+        max_threshold: 30.0,
+        ..settings
+    };
+    assert_eq!(step.sensitivity(29.0), 10.0);
+    assert_eq!(step.sensitivity(30.0), 40.0);
+
+    assert!(GyroSettings {
+        min_threshold: 50.0,
+        max_threshold: 10.0,
+        ..GyroSettings::default()
+    }
+    .validate()
+    .is_err());
+}
+
+#[test]
+fn soft_deadzone_and_smoothing() {
+    let settings = GyroSettings {
+        deadzone: 2.0,
+        smooth_threshold: 0.0,
+        ..GyroSettings::default()
+    };
+    let mut filter = GyroFilter::new();
+    // デッドゾーン内は大きさの二乗に比例し、境界で連続する
+    assert_near(
+        filter.apply(&settings, vec2(1.0, 0.0), 0.005),
+        vec2(0.5, 0.0),
+    );
+    assert_near(
+        filter.apply(&settings, vec2(0.0, 2.0), 0.005),
+        vec2(0.0, 2.0),
+    );
+
+    let settings = GyroSettings {
+        deadzone: 0.0,
+        smooth_threshold: 10.0,
+        smooth_time: 0.02,
+        ..GyroSettings::default()
+    };
+    let mut filter = GyroFilter::new();
+    // 遅い入力は4サンプルの平均
+    assert_near(
+        filter.apply(&settings, vec2(4.0, 0.0), 0.005),
+        vec2(4.0, 0.0),
+    );
+    for _ in 0..3 {
+        filter.apply(&settings, vec2(0.0, 0.0), 0.005);
+    }
+    assert_near(
+        filter.apply(&settings, vec2(4.0, 0.0), 0.005),
+        vec2(1.0, 0.0),
+    );
+    // 速い入力はそのまま
+    let mut filter = GyroFilter::new();
+    assert_near(
+        filter.apply(&settings, vec2(0.0, -30.0), 0.005),
+        vec2(0.0, -30.0),
+    );
+}
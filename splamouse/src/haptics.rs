@@ -0,0 +1,378 @@
+use crate::output::Output;
+use anyhow::{bail, Result};
+use enigo::{Key, MouseButton};
+use joycon::joycon_sys::output::{RumbleData, RumbleSide};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// 振動の合図(後ろほど優先)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Cue {
+    /// ホイールが1ノッチ回った
+    Detent,
+    /// マウスボタンを押した
+    Click,
+    /// ジャイロのクラッチが効いた(壁に当たった感触)
+    Ratchet,
+    /// 感度を変えた
+    Sensitivity,
+    /// プロファイルを切り替えた
+    Profile,
+}
+
+/// 一定の周波数・強さの振動
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Pulse {
+    /// 周波数(Hz)
+    pub freq: f32,
+    /// 強さ(0〜1、0 なら休み)
+    pub amp: f32,
+    /// 長さ(s)
+    pub duration: f64,
+}
+
+impl Pulse {
+    fn new(freq: f32, amp: f32, duration: f64) -> Self {
+        Pulse {
+            freq,
+            amp,
+            duration,
+        }
+    }
+}
+
+impl Default for Pulse {
+    fn default() -> Self {
+        Pulse::new(160.0, 0.0, 0.0)
+    }
+}
+
+/// プロファイルの `[haptics]` の設定
+///
+/// 合図ごとに振動のパターン(順番に鳴らす)を指定する。空にするとその合図は鳴らさない。
+/// 振動は求めていない人も多いので、`enabled` はデフォルトでは無効。
+/// ただし感度の変更は操作の確認なので、`enabled` に関わらず鳴らす。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HapticSettings {
+    pub enabled: bool,
+    pub detent: Vec<Pulse>,
+    pub click: Vec<Pulse>,
+    pub ratchet: Vec<Pulse>,
+    pub sensitivity: Vec<Pulse>,
+    pub profile: Vec<Pulse>,
+}
+
+impl HapticSettings {
+    pub fn validate(&self) -> Result<()> {
+        for pulse in [
+            &self.detent,
+            &self.click,
+            &self.ratchet,
+            &self.sensitivity,
+            &self.profile,
+        ]
+        .iter()
+        .copied()
+        .flatten()
+        {
+            if !(0.0..=1.0).contains(&pulse.amp) {
+                bail!("haptic amp must be between 0 and 1 (got {})", pulse.amp);
+            }
+            if !(pulse.duration >= 0.0 && pulse.duration.is_finite()) || pulse.freq <= 0.0 {
+                bail!("haptic freq must be positive and duration must not be negative");
+            }
+        }
+        Ok(())
+    }
+
+    fn pattern(&self, cue: Cue) -> &[Pulse] {
+        match cue {
+            Cue::Detent => &self.detent,
+            Cue::Click => &self.click,
+            Cue::Ratchet => &self.ratchet,
+            Cue::Sensitivity => &self.sensitivity,
+            Cue::Profile => &self.profile,
+        }
+    }
+}
+
+impl Default for HapticSettings {
+    fn default() -> Self {
+        HapticSettings {
+            enabled: false,
+            detent: vec![Pulse::new(250.0, 0.25, 0.012)],
+            click: vec![Pulse::new(180.0, 0.5, 0.025)],
+            ratchet: vec![Pulse::new(80.0, 0.8, 0.04)],
+            sensitivity: vec![Pulse::new(220.0, 0.6, 0.05)],
+            profile: vec![
+                Pulse::new(160.0, 0.7, 0.08),
+                Pulse::new(160.0, 0.0, 0.06),
+                Pulse::new(160.0, 0.7, 0.08),
+            ],
+        }
+    }
+}
+
+/// 振動のスケジューラ(時刻は呼び出し側が渡す)
+#[derive(Debug, Default)]
+struct Scheduler {
+    settings: HapticSettings,
+    /// 鳴らしている合図と、まだ鳴らし終えていない振動
+    playing: Option<(Cue, VecDeque<Pulse>)>,
+    /// 先頭の振動の終了時刻(まだ送っていなければ `None`)
+    until: Option<Duration>,
+    /// 最後にコントローラーに送った振動(周波数, 強さ)
+    sent: Option<(f32, f32)>,
+}
+
+impl Scheduler {
+    fn play(&mut self, cue: Cue) {
+        if !self.settings.enabled && cue != Cue::Sensitivity {
+            return;
+        }
+        // 優先度の高い合図は遮らない
+        if let Some((playing, _)) = &self.playing {
+            if *playing > cue {
+                return;
+            }
+        }
+        let pulses = self.settings.pattern(cue).iter().copied().collect();
+        self.playing = Some((cue, pulses));
+        self.until = None;
+    }
+
+    /// 時刻 `now` に鳴らすべき振動(周波数, 強さ)。変化がなければ `None`。
+    ///
+    /// 各振動は送った時から長さの分だけ鳴らすので、呼び出しの間隔より短い振動も必ず一度は送る。
+    fn poll(&mut self, now: Duration) -> Option<(f32, f32)> {
+        if let Some((_, pulses)) = &mut self.playing {
+            if self.until.is_some_and(|until| until <= now) {
+                pulses.pop_front();
+                self.until = None;
+            }
+        }
+        let pulse = self
+            .playing
+            .as_ref()
+            .and_then(|(_, pulses)| pulses.front())
+            .copied();
+        match pulse {
+            Some(pulse) if self.until.is_none() => {
+                self.until = Some(now + Duration::from_secs_f64(pulse.duration));
+            }
+            Some(_) => {}
+            None => self.playing = None,
+        }
+        // 止める時は一度だけ送る
+        let current = match pulse {
+            Some(pulse) if pulse.amp > 0.0 => (pulse.freq, pulse.amp),
+            _ => (160.0, 0.0),
+        };
+        if self.sent == Some(current) || (self.sent.is_none() && current.1 == 0.0) {
+            return None;
+        }
+        self.sent = Some(current);
+        Some(current)
+    }
+}
+
+/// どのスレッドからでも合図を出せる振動のキュー
+///
+/// 合図はすぐに返り、コントローラーへの書き込みは入力スレッドが数ms毎に `poll` で行う。
+/// 複製したものは同じスケジューラを共有する。
+#[derive(Debug, Clone)]
+pub struct Haptics {
+    scheduler: Arc<Mutex<Scheduler>>,
+    start: Instant,
+}
+
+impl Haptics {
+    pub fn new(settings: HapticSettings) -> Self {
+        Haptics {
+            scheduler: Arc::new(Mutex::new(Scheduler {
+                settings,
+                ..Scheduler::default()
+            })),
+            start: Instant::now(),
+        }
+    }
+
+    /// プロファイルを切り替えた時に設定を変える
+    pub fn set_settings(&self, settings: HapticSettings) {
+        self.scheduler.lock().unwrap().settings = settings;
+    }
+
+    pub fn play(&self, cue: Cue) {
+        self.scheduler.lock().unwrap().play(cue);
+    }
+
+    /// 振動を変える必要があれば、コントローラーに送るデータを返す
+    pub fn poll(&self) -> Option<RumbleData> {
+        let (freq, amp) = self.scheduler.lock().unwrap().poll(self.start.elapsed())?;
+        let side = RumbleSide::from_freq(freq, amp, freq, amp);
+        Some(RumbleData {
+            left: side,
+            right: side,
+        })
+    }
+}
+
+/// 出力にあわせて振動の合図を出す
+///
+/// コントローラーごとに出力スレッドの手前で使い、ホイールの1ノッチごととマウスボタンの押下で鳴らす。
+pub struct HapticOutput {
+    output: Box<dyn Output>,
+    haptics: Haptics,
+    /// 次のノッチまでのホイールの移動量(x, y)
+    wheel: [f64; 2],
+}
+
+impl HapticOutput {
+    pub fn new(output: Box<dyn Output>, haptics: Haptics) -> Self {
+        HapticOutput {
+            output,
+            haptics,
+            wheel: [0.0, 0.0],
+        }
+    }
+}
+
+impl Output for HapticOutput {
+    fn key_down(&mut self, key: Key) -> Result<()> {
+        self.output.key_down(key)
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<()> {
+        self.output.key_up(key)
+    }
+
+    fn mouse_down(&mut self, button: MouseButton) -> Result<()> {
+        self.haptics.play(Cue::Click);
+        self.output.mouse_down(button)
+    }
+
+    fn mouse_up(&mut self, button: MouseButton) -> Result<()> {
+        self.output.mouse_up(button)
+    }
+
+    fn mouse_click(&mut self, button: MouseButton) -> Result<()> {
+        self.haptics.play(Cue::Click);
+        self.output.mouse_click(button)
+    }
+
+    fn scroll(&mut self, x: f64, y: f64) -> Result<()> {
+        let mut detent = false;
+        for (wheel, delta) in self.wheel.iter_mut().zip([x, y]) {
+            // 向きが変わったら数え直す
+            if *wheel * delta < 0.0 {
+                *wheel = 0.0;
+            }
+            *wheel += delta;
+            while wheel.abs() >= 1.0 - 1e-9 {
+                *wheel -= wheel.signum();
+                detent = true;
+            }
+        }
+        if detent {
+            self.haptics.play(Cue::Detent);
+        }
+        self.output.scroll(x, y)
+    }
+
+    fn scroll_resolution(&self) -> f64 {
+        self.output.scroll_resolution()
+    }
+
+    fn move_relative(&mut self, x: i32, y: i32) -> Result<()> {
+        self.output.move_relative(x, y)
+    }
+
+    fn move_absolute(&mut self, x: i32, y: i32) -> Result<()> {
+        self.output.move_absolute(x, y)
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.output.text(text)
+    }
+
+    fn key_gap(&self) -> Duration {
+        self.output.key_gap()
+    }
+}
+
+#[cfg(test)]
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+#[test]
+fn schedule_cues() {
+    let mut scheduler = Scheduler::default();
+    scheduler.settings.enabled = true;
+    assert_eq!(scheduler.poll(ms(0)), None);
+
+    // 2回鳴らすパターン(各振動は送った時から数える)
+    scheduler.play(Cue::Profile);
+    assert_eq!(scheduler.poll(ms(10)), Some((160.0, 0.7)));
+    assert_eq!(scheduler.poll(ms(50)), None);
+    assert_eq!(scheduler.poll(ms(90)), Some((160.0, 0.0)));
+    // 優先度の低い合図で遮らない
+    scheduler.play(Cue::Detent);
+    assert_eq!(scheduler.poll(ms(145)), None);
+    assert_eq!(scheduler.poll(ms(150)), Some((160.0, 0.7)));
+    assert_eq!(scheduler.poll(ms(230)), Some((160.0, 0.0)));
+    assert_eq!(scheduler.poll(ms(300)), None);
+
+    scheduler.play(Cue::Detent);
+    assert_eq!(scheduler.poll(ms(305)), Some((250.0, 0.25)));
+    scheduler.play(Cue::Click);
+    assert_eq!(scheduler.poll(ms(310)), Some((180.0, 0.5)));
+    assert_eq!(scheduler.poll(ms(340)), Some((160.0, 0.0)));
+
+    // 呼び出しの間隔より短い振動も、遅れて一度は送る
+    scheduler.play(Cue::Detent);
+    assert_eq!(scheduler.poll(ms(420)), Some((250.0, 0.25)));
+    assert_eq!(scheduler.poll(ms(425)), None);
+    assert_eq!(scheduler.poll(ms(432)), Some((160.0, 0.0)));
+
+    // 無効にしても、感度の変更だけは知らせる
+    scheduler.settings.enabled = false;
+    scheduler.play(Cue::Click);
+    assert_eq!(scheduler.poll(ms(500)), None);
+    scheduler.play(Cue::Sensitivity);
+    assert_eq!(scheduler.poll(ms(500)), Some((220.0, 0.6)));
+}
+
+#[test]
+fn detents_and_clicks() {
+    use crate::output::Recording;
+
+    let haptics = Haptics::new(HapticSettings {
+        enabled: true,
+        ..HapticSettings::default()
+    });
+    let mut output = HapticOutput::new(Box::new(Recording::new()), haptics.clone());
+    let playing = || {
+        let mut scheduler = haptics.scheduler.lock().unwrap();
+        scheduler.playing.take().map(|(cue, _)| cue)
+    };
+
+    output.scroll(0.0, 0.5).unwrap();
+    assert_eq!(playing(), None);
+    output.scroll(0.0, 0.5).unwrap();
+    assert_eq!(playing(), Some(Cue::Detent));
+    output.scroll(0.0, -0.75).unwrap();
+    assert_eq!(playing(), None);
+    output
+        .apply(crate::output::Event::MouseDown {
+            button: MouseButton::Left,
+        })
+        .unwrap();
+    assert_eq!(playing(), Some(Cue::Click));
+}
@@ -0,0 +1,89 @@
+use enigo::Key;
+
+/// 一文字のキーを、修飾キーと組み合わせても確実に効くOSのキーコードに変換する
+///
+/// macOSではUS配列の位置の仮想キーコードで送る。他のOSではenigo・uinputがそのまま変換する。
+pub fn platform_key(key: Key) -> Key {
+    #[cfg(target_os = "macos")]
+    if let Key::Layout(c) = key {
+        if let Some(code) = mac_keycode(c) {
+            return Key::Raw(code);
+        }
+    }
+    key
+}
+
+/// macOSの仮想キーコード(kVK_ANSI_*)
+#[cfg_attr(not(any(test, target_os = "macos")), allow(dead_code))]
+fn mac_keycode(c: char) -> Option<u16> {
+    let code = match c.to_ascii_lowercase() {
+        'a' => 0x00,
+        's' => 0x01,
+        'd' => 0x02,
+        'f' => 0x03,
+        'h' => 0x04,
+        'g' => 0x05,
+        'z' => 0x06,
+        'x' => 0x07,
+        'c' => 0x08,
+        'v' => 0x09,
+        'b' => 0x0B,
+        'q' => 0x0C,
+        'w' => 0x0D,
+        'e' => 0x0E,
+        'r' => 0x0F,
+        'y' => 0x10,
+        't' => 0x11,
+        '1' => 0x12,
+        '2' => 0x13,
+        '3' => 0x14,
+        '4' => 0x15,
+        '6' => 0x16,
+        '5' => 0x17,
+        '=' => 0x18,
+        '9' => 0x19,
+        '7' => 0x1A,
+        '-' => 0x1B,
+        '8' => 0x1C,
+        '0' => 0x1D,
+        ']' => 0x1E,
+        'o' => 0x1F,
+        'u' => 0x20,
+        '[' => 0x21,
+        'i' => 0x22,
+        'p' => 0x23,
+        'l' => 0x25,
+        'j' => 0x26,
+        '\'' => 0x27,
+        'k' => 0x28,
+        ';' => 0x29,
+        '\\' => 0x2A,
+        ',' => 0x2B,
+        '/' => 0x2C,
+        'n' => 0x2D,
+        'm' => 0x2E,
+        '.' => 0x2F,
+        '`' => 0x32,
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[test]
+fn mac_keycodes() {
+    // 以前のデフォルトプロファイルの生のキーコード
+    assert_eq!(mac_keycode('w'), Some(0x0D));
+    assert_eq!(mac_keycode('R'), Some(0x0F));
+    assert_eq!(mac_keycode('c'), Some(0x08));
+    assert_eq!(mac_keycode('v'), Some(0x09));
+    assert_eq!(mac_keycode('z'), Some(0x06));
+    assert_eq!(mac_keycode('é'), None);
+
+    let key = platform_key(Key::Layout('w'));
+    if cfg!(target_os = "macos") {
+        assert_eq!(key, Key::Raw(0x0D));
+    } else {
+        assert_eq!(key, Key::Layout('w'));
+    }
+    assert_eq!(platform_key(Key::Tab), Key::Tab);
+}
@@ -1,76 +1,311 @@
-use anyhow::{Context, Result};
+mod controller;
+mod gesture;
+mod gyro;
+mod haptics;
+mod keys;
+mod mapper;
+mod output;
+mod pointer;
+mod precision;
+mod profile;
+mod queue;
+mod scroll;
+mod sensitivity;
+mod share;
+mod stick;
+mod timer;
+#[cfg(target_os = "linux")]
+mod uinput;
+
+use anyhow::{anyhow, Context, Result};
+use cgmath::vec2;
 use clap::Parser;
-use enigo::*;
+use controller::{Controller, PairSpec};
+use gyro::GyroFilter;
+use haptics::{Cue, HapticOutput, Haptics};
+use hid_gamepad::sys::KeyStatus;
 use joycon::{
-    hidapi::HidApi,
+    hidapi::{DeviceInfo, HidApi},
     joycon_sys::{
-        input::{BatteryLevel },
+        input::BatteryLevel,
         light::{self, PlayerLight},
-        HID_IDS, NINTENDO_VENDOR_ID,
     },
-    JoyCon,
+    Grip, JoyConPair,
 };
-use std::sync::{Mutex, Arc};
+use mapper::Mapper;
+use output::{Backend, Output};
+use pointer::{AbsolutePointer, Desktop, Monitor, PointerMode};
+use profile::{Action, Profile};
+use queue::Queue;
+use scroll::{KineticScroll, ScrollMode};
+use sensitivity::{LevelStore, Levels};
+use share::{Arbiter, Arbitrated, Policy};
+use std::sync::{Arc, Mutex};
 use std::{
-    time::Duration,
+    any::Any,
+    collections::HashSet,
+    ffi::CString,
+    panic::{self, catch_unwind, AssertUnwindSafe},
+    path::PathBuf,
     thread,
+    time::{Duration, Instant},
 };
+use stick::{FlickStick, RightStickMode};
+use timer::Timer;
 
 #[derive(Parser)]
 struct Opts {
-    #[clap(short, long, default_value="0.0")]
-    pub gyro: f64,
-    #[clap(short, long, default_value="0.0")]
+    #[clap(short, long, default_value = "0.0")]
     pub stick: f64,
+    /// ボタン割り当てのプロファイル(TOML)
+    ///
+    /// 複数指定すると、`profile:next` / `profile:prev` を割り当てたボタンで切り替えられる。
+    /// `controllers` を書いたプロファイルは、そのコントローラーだけが使う。
+    #[clap(short, long)]
+    pub profile: Vec<PathBuf>,
+    /// キー入力・マウス操作の出力方法
+    #[clap(short, long, arg_enum, default_value = "enigo")]
+    pub output: Backend,
+    /// 絶対座標モードで使うモニター(`1920x1080+0+0` の形式、最初のものが中心)
+    ///
+    /// 複数のモニターを並べる場合は、それぞれ指定する。省略するとメインのモニターを使う。
+    #[clap(long)]
+    pub monitor: Vec<Monitor>,
+    /// Joy-Conを1本だけ使う時の持ち方(`vertical` か `sideways`)
+    ///
+    /// `sideways` ではレールを奥にして横に持ち、スティックとジャイロの向きをあわせ、SL・SR を L・R として使う。
+    #[clap(long, default_value = "vertical")]
+    pub grip: Grip,
+    /// 複数のコントローラーの出力のまとめ方(`merge` か `last-active`)
+    ///
+    /// `merge` は全ての操作を重ね、`last-active` は最後に操作を始めたコントローラーだけを通す。
+    #[clap(long, default_value = "merge")]
+    pub policy: Policy,
+    /// 1台のコントローラーとして使う左右のJoy-Con(`左のMACアドレス+右のMACアドレス`)
+    ///
+    /// MACアドレスは接続時に表示される。指定のないJoy-Conは1本ずつ別のコントローラーとして使う。
+    #[clap(long)]
+    pub pair: Vec<PairSpec>,
+}
+
+/// 全てのコントローラーで共有するもの
+#[derive(Clone)]
+struct Shared {
+    /// コントローラーごとに保存した感度の段階
+    store: Arc<Mutex<LevelStore>>,
+    /// 絶対座標モードでカーソルを動かす範囲
+    desktop: Desktop,
+    /// 出力スレッド(全てのコントローラーの出力を順番に実行する)
+    queue: Queue,
+    arbiter: Arbiter,
+    /// スティックの感度
+    stick: f64,
+}
+
+impl Shared {
+    /// コントローラー `id` の出力(振動の合図を出し、方針に従って共有の出力へ送る)
+    fn output(&self, id: &str, haptics: &Haptics) -> Arbitrated {
+        let output = HapticOutput::new(Box::new(self.queue.clone()), haptics.clone());
+        self.arbiter.output(id, Box::new(output))
+    }
 }
 
 fn main() -> Result<()> {
     let opts = Opts::parse();
 
-    if opts.gyro.abs() > 5.0 || opts.stick.abs() > 5.0 {
-        eprintln!("gyro and stick must be between -5.0 and 5.0");
+    if opts.stick.abs() > 5.0 {
+        eprintln!("stick must be between -5.0 and 5.0");
         return Ok(());
     }
 
-    // 感度計算
-    let gyro = 2.0 + opts.gyro * 0.2;
+    // 感度計算(ジャイロの感度はプロファイルで設定する)
     let stick = 2.0 + opts.stick * 0.2;
 
+    // ボタン割り当て(接続前に検証する)
+    let mut profiles = opts
+        .profile
+        .iter()
+        .map(|path| Profile::load(path))
+        .collect::<Result<Vec<_>>>()?;
+    if profiles.is_empty() {
+        profiles.push(Profile::default());
+    }
+
+    // コントローラーごとに保存した感度の段階
+    let store =
+        LevelStore::load(&LevelStore::default_path().context("cannot find the config directory")?)?;
+
+    // 絶対座標モードでカーソルを動かす範囲
+    let desktop = if opts.monitor.is_empty() {
+        Desktop::detect()
+    } else {
+        Desktop::new(opts.monitor.clone())?
+    };
+
+    // 出力スレッド(uinputの権限がない場合などは接続前に終了する)
+    // キー入力の間隔を待っても、入力の処理は止めない
+    let queue = {
+        let desktop = desktop.clone();
+        let backend = opts.output;
+        Queue::spawn(move || backend.open(&desktop))?
+    };
+    let shared = Shared {
+        store: Arc::new(Mutex::new(store)),
+        desktop,
+        queue,
+        arbiter: Arbiter::new(opts.policy),
+        stick,
+    };
+
+    // 使用中のデバイス(HIDのパス)
+    let busy = Arc::new(Mutex::new(HashSet::new()));
+    // 開けなかったデバイス(毎秒開き直すが、エラーは一度だけ表示する)
+    let mut failed = HashSet::new();
+
     let mut api = HidApi::new()?;
     loop {
         api.refresh_devices()?;
-        if let Some(device_info) = api
+        let used = busy.lock().unwrap().clone();
+        // hid-gamepad が対応しているコントローラーだけを開く
+        let free: Vec<&DeviceInfo> = api
             .device_list()
-            .find(|x| x.vendor_id() == NINTENDO_VENDOR_ID && HID_IDS.contains(&x.product_id()))
-        {
-            let device = device_info
-                .open_device(&api)
-                .with_context(|| format!("error opening the HID device {:?}", device_info))?;
-
-            // NOTE: 接続が中途半端な際、ここでよくパニックする。
-            let _ = std::panic::catch_unwind(|| -> Result<()> {
-                let joycon = JoyCon::new(device, device_info.clone())?;
-                hid_main(joycon, gyro, stick).context("error running the command")?;
-                Ok(())
-            });
-        } else {
+            .filter(|x| !used.contains(x.path()) && hid_gamepad::is_supported(x))
+            .collect();
+        // 外れたデバイスは、次に接続した時にまたエラーを表示する
+        failed.retain(|path: &CString| free.iter().any(|x| x.path() == path.as_c_str()));
+        // `--pair` で指定した左右のJoy-Conが両方空いていれば、1台のコントローラーとして両方のスティックを使う
+        if let Some((left, right)) = opts.pair.iter().find_map(|pair| pair.find(&free)) {
+            match catch_unwind(AssertUnwindSafe(|| {
+                Controller::pair(JoyConPair::open_devices(&api, left, right)?)
+            })) {
+                Ok(Ok(controller)) => {
+                    spawn(
+                        controller,
+                        vec![left.path().to_owned(), right.path().to_owned()],
+                        &profiles,
+                        &shared,
+                        &busy,
+                    );
+                    continue;
+                }
+                Ok(Err(e)) => {
+                    if failed.insert(left.path().to_owned()) {
+                        eprintln!("error opening the Joy-Cons: {:?}", e);
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+
+        // 対応しているコントローラーを全て使う
+        // NOTE: 接続が中途半端な際、ここでよくパニックする。
+        // (`--pair` で指定したJoy-Conは、もう一方が接続するまで待つ)
+        for device_info in free {
+            if opts.pair.iter().any(|pair| pair.contains(device_info)) {
+                continue;
+            }
+            match catch_unwind(AssertUnwindSafe(|| {
+                Controller::open(&api, device_info, opts.grip)
+            })) {
+                Ok(Ok(Some(controller))) => {
+                    spawn(
+                        controller,
+                        vec![device_info.path().to_owned()],
+                        &profiles,
+                        &shared,
+                        &busy,
+                    );
+                }
+                Ok(Ok(None)) | Err(_) => {}
+                Ok(Err(e)) => {
+                    if failed.insert(device_info.path().to_owned()) {
+                        eprintln!("error opening the HID device {:?}: {:?}", device_info, e);
+                    }
+                }
+            }
+        }
+        if busy.lock().unwrap().is_empty() {
             eprintln!("No device found");
-            thread::sleep(Duration::from_secs(1));
         }
+        thread::sleep(Duration::from_secs(1));
     }
 }
 
-fn hid_main(mut joycon: JoyCon, gyro: f64, stick: f64) -> Result<()> {
-    joycon.set_home_light(light::HomeLight::new(
+/// コントローラーごとのスレッドを起動する(切断したら `paths` を `busy` から外す)
+fn spawn(
+    controller: Controller,
+    paths: Vec<CString>,
+    profiles: &[Profile],
+    shared: &Shared,
+    busy: &Arc<Mutex<HashSet<CString>>>,
+) {
+    busy.lock().unwrap().extend(paths.iter().cloned());
+    let id = controller.id().to_string();
+    let profiles = profile::for_controller(profiles, &id);
+    eprintln!("Connected: {} (profile: {})", id, profiles[0].name);
+    let shared = shared.clone();
+    let busy = Arc::clone(busy);
+    thread::spawn(move || {
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            hid_main(controller, &profiles, &shared)
+        }))
+        .unwrap_or_else(|panic| Err(panic_error(panic)))
+        .context("error running the command");
+        if let Err(e) = result {
+            eprintln!("{:?}", e);
+        }
+        eprintln!("Disconnected: {}", id);
+        shared.arbiter.release(&id);
+        let mut busy = busy.lock().unwrap();
+        for path in &paths {
+            busy.remove(path);
+        }
+    });
+}
+
+/// パニックの内容をエラーにする
+fn panic_error(panic: Box<dyn Any + Send>) -> anyhow::Error {
+    let message = match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    };
+    anyhow!("panicked: {}", message)
+}
+
+fn hid_main(mut controller: Controller, profiles: &[Profile], shared: &Shared) -> Result<()> {
+    controller.set_home_light(light::HomeLight::new(
         0x8,
         0x2,
         0x0,
         &[(0xf, 0xf, 0), (0x2, 0xf, 0)],
     ))?;
 
-    let battery_level = joycon.tick()?.info.battery_level();
+    // プロファイルが複数ある場合はプロファイル、それ以外は電池残量を表示
+    let battery = controller.battery_level()?;
+    controller.set_player_light(base_lights(profiles, 0, battery))?;
 
-    joycon.set_player_light(light::PlayerLights::new(
+    monitor(&mut controller, profiles, shared, battery)?;
+    Ok(())
+}
+
+/// 普段表示するプレイヤーランプ(プロファイルが複数ある場合や電池残量がわからない場合はプロファイル)
+fn base_lights(
+    profiles: &[Profile],
+    current: usize,
+    battery: Option<BatteryLevel>,
+) -> light::PlayerLights {
+    match battery {
+        Some(battery_level) if profiles.len() == 1 => battery_lights(battery_level),
+        _ => profile_lights(current),
+    }
+}
+
+/// 電池残量を示すプレイヤーランプ
+fn battery_lights(battery_level: BatteryLevel) -> light::PlayerLights {
+    light::PlayerLights::new(
         (battery_level >= BatteryLevel::Full).into(),
         (battery_level >= BatteryLevel::Medium).into(),
         (battery_level >= BatteryLevel::Low).into(),
@@ -79,22 +314,62 @@ fn hid_main(mut joycon: JoyCon, gyro: f64, stick: f64) -> Result<()> {
         } else {
             PlayerLight::Blinking
         },
-    ))?;
+    )
+}
 
-    monitor(&mut joycon, gyro, stick)?;
-    Ok(())
+/// n番目のプロファイルを示すプレイヤーランプ(5番目以降は点滅)
+fn profile_lights(index: usize) -> light::PlayerLights {
+    let light = |i: usize| {
+        if index % 4 != i {
+            PlayerLight::Off
+        } else if index < 4 {
+            PlayerLight::On
+        } else {
+            PlayerLight::Blinking
+        }
+    };
+    light::PlayerLights::new(light(0), light(1), light(2), light(3))
 }
 
-fn monitor(joycon: &mut JoyCon, gyro: f64, stick: f64) -> Result<()> {
-    joycon.enable_imu()?;
-    joycon.load_calibration()?;
+fn monitor(
+    controller: &mut Controller,
+    profiles: &[Profile],
+    shared: &Shared,
+    battery: Option<BatteryLevel>,
+) -> Result<()> {
+    // このコントローラーで前回選んだ感度
+    let id = controller.id().to_string();
+    let mut levels = shared.store.lock().unwrap().get(&id);
+    let (desktop, stick) = (&shared.desktop, shared.stick);
+
+    // 振動の合図(コントローラーへの書き込みは状態取得スレッドで行う)
+    let haptics = Haptics::new(profiles[0].haptics.clone());
+    // 感度の変更は `enabled` に関わらず振動で知らせる
+    if profiles
+        .iter()
+        .any(|profile| profile.haptics.enabled || !profile.haptics.sensitivity.is_empty())
+    {
+        controller.enable_vibration(true)?;
+    }
+
+    // マクロの待ち時間はこのコントローラーのタイマーで待ち、出力スレッドを止めない
+    let timer = {
+        let (shared, id, haptics) = (shared.clone(), id.clone(), haptics.clone());
+        Timer::spawn(move || Box::new(shared.output(&id, &haptics)))
+    };
 
+    // 各スレッドの出力は、他のコントローラーと共有の出力スレッドに送る
+    let (id, haptics, timer) = (&id, &haptics, &timer);
     thread::scope(|s| {
-        // ジャイロの値
+        // ジャイロの値(プロファイルの設定で変換したカーソルの速度 px/s)
+        let gx = Arc::new(Mutex::new(0.0));
         let gy = Arc::new(Mutex::new(0.0));
-        let gz = Arc::new(Mutex::new(0.0));
+        let _gx = Arc::clone(&gx);
         let _gy = Arc::clone(&gy);
-        let _gz = Arc::clone(&gz);
+
+        // 前後の軸の周りの角速度(deg/s、スクロールのひねり)
+        let twist = Arc::new(Mutex::new(0.0));
+        let _twist = Arc::clone(&twist);
 
         // L-スティックの値
         let slx = Arc::new(Mutex::new(0.0));
@@ -108,9 +383,25 @@ fn monitor(joycon: &mut JoyCon, gyro: f64, stick: f64) -> Result<()> {
         let _srx = Arc::clone(&srx);
         let _sry = Arc::clone(&sry);
 
-        // コントローラーの姿勢
-        let rot = Arc::new(Mutex::new(0.0));
-        let _rot = Arc::clone(&rot);
+        // 現在のプロファイル
+        let current = Arc::new(Mutex::new(0));
+        let _current = Arc::clone(&current);
+
+        // スティック感度の倍率
+        let stick_factor = Arc::new(Mutex::new(levels.stick_factor()));
+        let _stick_factor = Arc::clone(&stick_factor);
+
+        // 精密モードのカーソル移動の倍率(押していなければ 1)
+        let precision = Arc::new(Mutex::new(1.0));
+        let _precision = Arc::clone(&precision);
+
+        // 絶対座標モードのカーソルの位置(相対モードでは `None`)
+        let absolute = Arc::new(Mutex::new(None));
+        let _absolute = Arc::clone(&absolute);
+
+        // ジャイロの有効・無効(クラッチ)
+        let gyro_enabled = Arc::new(Mutex::new(true));
+        let _gyro_enabled = Arc::clone(&gyro_enabled);
 
         // 割り込みシグナル
         let interrupt = Arc::new(Mutex::new(false));
@@ -118,353 +409,160 @@ fn monitor(joycon: &mut JoyCon, gyro: f64, stick: f64) -> Result<()> {
 
         // 状態取得スレッド(コントローラーの状況によって固まる)
         let handler = s.spawn(move || -> Result<()> {
-            let mut enigo = Enigo::new();
-
-            // ボタンの状態
-            let mut a = false;
-            let mut b = false;
-            let mut x = false;
-            let mut y = false;
-            let mut l = false;
-            let mut r = false;
-            let mut zl = false;
-            let mut zr = false;
-            let mut minus = false;
-            let mut plus = false;
-            let mut left = false;
-            let mut right = false;
-            let mut down = false;
-            let mut up = false;
-            let mut capture = false;
-            let mut home = false;
-            let mut lstick = false;
-            let mut rstick = false;
+            let mut output = shared.output(id, haptics);
+
+            // ボタンの状態(長押しなどの判定は起動からの時刻で行う)
+            let mut mapper = Mapper::new(profiles);
+            let start = Instant::now();
+
+            // ジャイロのスムージング
+            let mut filter = GyroFilter::new();
+
+            // 感度を表示している間は、この時刻まで元のランプに戻さない
+            let mut level_lights_until = None;
+
+            // 絶対座標モードの正面の向き
+            let mut pointer = AbsolutePointer::new();
+            let mut last_report = Instant::now();
 
             loop {
-                let mut should_sleep = false;
+                // 振動の開始・停止(合図は他のスレッドからも届くので、レポートを待つ間も数ms毎に送る)
+                if let Some(rumble) = haptics.poll() {
+                    controller.set_rumble(rumble)?;
+                }
+                let report = match controller.recv_timeout(Duration::from_millis(5))? {
+                    Some(report) => report,
+                    None => continue,
+                };
+                let gravity = controller.gravity();
+                if controller.gyro_recalibrated() {
+                    eprintln!("Gyro recalibrated: {}", controller.id());
+                }
 
-                let report = joycon.tick()?;
+                // ジャイロの値(ジャイロと加速度センサを統合した重力の向きで座標系を変換)
+                // 精密モード中はスムージングを強める
+                let precision_gyro;
+                let settings = if mapper.precision() {
+                    precision_gyro = mapper.profile().precision.gyro(&mapper.profile().gyro);
+                    &precision_gyro
+                } else {
+                    &mapper.profile().gyro
+                };
+                let sample_duration = 1.0 / report.frequency as f64;
+                for frame in &report.motion {
+                    let motion = settings
+                        .space
+                        .transform(frame.rotation_speed.as_vec(), gravity);
+                    // 微量のモーションは弱め、遅い動きは手ぶれを均す
+                    let motion = filter.apply(settings, motion, sample_duration);
+                    let motion = settings.velocity(motion) * levels.gyro_factor();
+
+                    let mut gx = _gx.lock().unwrap();
+                    *gx = motion.x;
 
-                // ジャイロの値
-                for frame in &report.imu.unwrap() {
                     let mut gy = _gy.lock().unwrap();
-                    *gy = frame.gyro.y;
+                    *gy = motion.y;
 
-                    let mut gz = _gz.lock().unwrap();
-                    *gz = frame.gyro.z;
-
-                    // コントローラーの姿勢(加速度センサでドリフト修正)
-                    let arot = (frame.accel[1] / frame.accel[2]).atan().to_degrees();
-                    let mut rot = _rot.lock().unwrap();
-                    *rot = (*rot - frame.gyro.x * 0.005) * 0.95 + arot * 0.05;
+                    *_twist.lock().unwrap() = frame.rotation_speed.z;
                 }
 
                 // L-スティックの値
                 let mut slx = _slx.lock().unwrap();
-                *slx = report.left_stick.x;
+                *slx = report.left_joystick.x;
                 let mut sly = _sly.lock().unwrap();
-                *sly = report.left_stick.y;
+                *sly = report.left_joystick.y;
 
                 // R-スティックの値
                 let mut srx = _srx.lock().unwrap();
-                *srx = report.right_stick.x;
+                *srx = report.right_joystick.x;
                 let mut sry = _sry.lock().unwrap();
-                *sry = report.right_stick.y;
-
-                // Aボタン押下時
-                if report.buttons.right.a() {
-                    if !a {
-                        enigo.key_down(Key::Meta);
-                        enigo.key_down(Key::RightArrow);
-                        should_sleep = true;
-                        a = true;
-                    }
-                } else {
-                    if a {
-                        enigo.key_up(Key::RightArrow);
-                        enigo.key_up(Key::Meta);
-                        should_sleep = true;
-                        a = false;
+                *sry = report.right_joystick.y;
+
+                // ボタンの押下・解放時にプロファイルの動作を実行
+                let current = mapper.current();
+                for (action, pressed) in mapper.update(start.elapsed(), |key| {
+                    report.keys[key] == KeyStatus::Pressed
+                }) {
+                    match action {
+                        // 感度の変更はランプと振動で知らせ、コントローラーごとに保存する
+                        Action::Sensitivity { target, step } if pressed => {
+                            let level = levels.step(target, step);
+                            *_stick_factor.lock().unwrap() = levels.stick_factor();
+                            eprintln!("Sensitivity: gyro {}, stick {}", levels.gyro, levels.stick);
+                            controller.set_player_light(Levels::lights(level))?;
+                            level_lights_until = Some(Instant::now() + Duration::from_secs(2));
+                            haptics.play(Cue::Sensitivity);
+                            if let Err(e) = shared.store.lock().unwrap().set(id, levels) {
+                                eprintln!("{:?}", e);
+                            }
+                        }
+                        // 今の向きを正面にする(相対モードではカーソルを中心に戻す)
+                        Action::Recenter if pressed => {
+                            pointer.recenter();
+                            if mapper.profile().pointer.mode == PointerMode::Relative {
+                                let center = desktop.primary().center();
+                                output.move_absolute(center.x as i32, center.y as i32)?;
+                            }
+                        }
+                        action => output::perform(&mut output, timer, &action, pressed)?,
                     }
                 }
 
-                // Bボタン押下時
-                if report.buttons.right.b() {
-                    if !b {
-                        enigo.key_down(Key::Meta);
-                        enigo.key_down(Key::LeftArrow);
-                        should_sleep = true;
-                        b = true;
-                    }
-                } else {
-                    if b {
-                        enigo.key_up(Key::LeftArrow);
-                        enigo.key_up(Key::Meta);
-                        should_sleep = true;
-                        b = false;
-                    }
+                // クラッチが効いた瞬間に振動で知らせる
+                let enabled = mapper.gyro_enabled();
+                let was_enabled = std::mem::replace(&mut *_gyro_enabled.lock().unwrap(), enabled);
+                if was_enabled && !enabled {
+                    haptics.play(Cue::Ratchet);
                 }
-
-                // マイナスボタン押下時
-                if report.buttons.middle.minus() {
-                    if !minus {
-                        enigo.key_down(Key::Meta);
-                        enigo.key_down(Key::Raw(0x0D));
-                        should_sleep = true;
-                        minus = true;
-                    }
-                } else {
-                    if minus {
-                        enigo.key_up(Key::Raw(0x0D));
-                        enigo.key_up(Key::Meta);
-                        should_sleep = true;
-                        minus = false;
+                *_current.lock().unwrap() = mapper.current();
+
+                // 絶対座標モードではコントローラーの向きでカーソルの位置を決める(クラッチ中は止める)
+                let dt = last_report.elapsed().as_secs_f64();
+                last_report = Instant::now();
+                let settings = &mapper.profile().pointer;
+                {
+                    let mut absolute = _absolute.lock().unwrap();
+                    if settings.mode != PointerMode::Absolute {
+                        *absolute = None;
+                    } else if enabled || absolute.is_none() {
+                        *absolute =
+                            Some(pointer.update(settings, desktop, controller.orientation(), dt));
                     }
                 }
-
-                // プラスボタン押下時
-                if report.buttons.middle.plus() {
-                    if !plus {
-                        enigo.key_down(Key::Meta);
-                        enigo.key_down(Key::Raw(0x0F));
-                        should_sleep = true;
-                        plus = true;
-                    }
+                *_precision.lock().unwrap() = if mapper.precision() {
+                    mapper.profile().precision.scale
                 } else {
-                    if plus {
-                        enigo.key_up(Key::Raw(0x0F));
-                        enigo.key_up(Key::Meta);
-                        should_sleep = true;
-                        plus = false;
-                    }
+                    1.0
+                };
+
+                // プロファイル切り替え
+                if mapper.current() != current {
+                    eprintln!("Profile: {}", mapper.profile().name);
+                    controller.set_player_light(profile_lights(mapper.current()))?;
+                    level_lights_until = None;
+                    haptics.set_settings(mapper.profile().haptics.clone());
+                    haptics.play(Cue::Profile);
+                    pointer.recenter();
                 }
 
-                // 左ボタン押下時
-                if report.buttons.left.left() {
-                    if !left {
-                        enigo.key_down(Key::Control);
-                        enigo.key_down(Key::Shift);
-                        enigo.key_down(Key::Tab);
-                        should_sleep = true;
-                        left = true;
-                    }
-                } else {
-                    if left {
-                        enigo.key_up(Key::Tab);
-                        enigo.key_up(Key::Shift);
-                        enigo.key_up(Key::Control);
-                        should_sleep = true;
-                        left = false;
-                    }
-                }
-
-                // 右ボタン押下時
-                if report.buttons.left.right() {
-                    if !right {
-                        enigo.key_down(Key::Control);
-                        enigo.key_down(Key::Tab);
-                        should_sleep = true;
-                        right = true;
-                    }
-                } else {
-                    if right {
-                        enigo.key_up(Key::Tab);
-                        enigo.key_up(Key::Control);
-                        should_sleep = true;
-                        right = false;
-                    }
-                }
-
-                // 下ボタン押下時
-                if report.buttons.left.down() {
-                    if !down {
-                        enigo.key_down(Key::Meta);
-                        enigo.key_down(Key::Raw(0x08));
-                        should_sleep = true;
-                        down = true;
-                    }
-                } else {
-                    if down {
-                        enigo.key_up(Key::Raw(0x08));
-                        enigo.key_up(Key::Meta);
-                        should_sleep = true;
-                        down = false;
-                    }
-                }
-
-                // 上ボタン押下時
-                if report.buttons.left.up() {
-                    if !up {
-                        enigo.key_down(Key::Meta);
-                        enigo.key_down(Key::Raw(0x09));
-                        should_sleep = true;
-                        up = true;
-                    }
-                } else {
-                    if up {
-                        enigo.key_up(Key::Raw(0x09));
-                        enigo.key_up(Key::Meta);
-                        should_sleep = true;
-                        up = false;
-                    }
-                }
-
-                // ホーム押下時
-                if report.buttons.middle.home() {
-                    if !home {
-                        enigo.key_down(Key::Meta);
-                        enigo.key_down(Key::Shift);
-                        enigo.key_down(Key::Raw(0x06));
-                        should_sleep = true;
-                        home = true;
-                    }
-                } else {
-                    if home {
-                        enigo.key_up(Key::Raw(0x06));
-                        enigo.key_up(Key::Shift);
-                        enigo.key_up(Key::Meta);
-                        should_sleep = true;
-                        home = false;
-                    }
-                }
-
-                // 撮影ボタン押下時
-                if report.buttons.middle.capture() {
-                    if !capture {
-                        enigo.key_down(Key::Meta);
-                        enigo.key_down(Key::Raw(0x06));
-                        should_sleep = true;
-                        capture = true;
-                    }
-                } else {
-                    if capture {
-                        enigo.key_up(Key::Raw(0x06));
-                        enigo.key_up(Key::Meta);
-                        should_sleep = true;
-                        capture = false;
-                    }
-                }
-
-                // L-stick押下時
-                if report.buttons.middle.lstick() {
-                    if !lstick {
-                        enigo.mouse_down(MouseButton::Middle);
-                        lstick = true;
-                    }
-                } else {
-                    if lstick {
-                        enigo.mouse_up(MouseButton::Middle);
-                        lstick = false;
-                    }
-                }
-
-                // R-stick押下時
-                if report.buttons.middle.rstick() {
-                    if !rstick {
-                        enigo.key_down(Key::Return);
-                        should_sleep = true;
-                        rstick = true;
-                    }
-                } else {
-                    if rstick {
-                        enigo.key_up(Key::Return);
-                        should_sleep = true;
-                        rstick = false;
-                    }
-                }
-
-                // Xボタン押下時
-                if report.buttons.right.x() {
-                    if !x {
-                        enigo.key_down(Key::Meta);
-                        should_sleep = true;
-                        x = true;
-                    }
-                } else {
-                    if x {
-                        enigo.key_up(Key::Meta);
-                        should_sleep = true;
-                        x = false;
-                    }
-                }
-
-                // Yボタン押下時
-                if report.buttons.right.y() {
-                    if !y {
-                        enigo.key_down(Key::Shift);
-                        should_sleep = true;
-                        y = true;
-                    }
-                } else {
-                    if y {
-                        enigo.key_up(Key::Shift);
-                        should_sleep = true;
-                        y = false;
-                    }
-                }
-
-                // ZRボタン押下時
-                if report.buttons.right.zr() {
-                    if !zr {
-                        zr = true;
-                    }
-                } else {
-                    if zr {
-                        enigo.mouse_click(MouseButton::Left);
-                        zr = false;
-                    }
-                }
-
-                // Lボタン押下時
-                if report.buttons.left.l() {
-                    if !l {
-                        enigo.key_down(Key::Alt);
-                        should_sleep = true;
-                        l = true;
-                    }
-                } else {
-                    if l {
-                        enigo.key_up(Key::Alt);
-                        should_sleep = true;
-                        l = false;
-                    }
-                }
-
-                // Rボタン押下時
-                if report.buttons.right.r() {
-                    if !r {
-                        r = true;
-                    }
-                } else {
-                    if r {
-                        enigo.mouse_click(MouseButton::Right);
-                        r = false;
-                    }
-                }
-
-                // ZLボタン押下時
-                if report.buttons.left.zl() {
-                    if !zl {
-                        enigo.mouse_down(MouseButton::Left);
-                        zl = true;
-                    }
-                } else {
-                    if zl {
-                        enigo.mouse_up(MouseButton::Left);
-                        zl = false;
-                    }
-                }
-
-                // キー入力の切れ目でスリープしないと、マシンスペックによって順番が前後してしまう。
-                if should_sleep {
-                    thread::sleep(Duration::from_millis(100));
+                // 感度の表示を元に戻す
+                if level_lights_until.is_some_and(|until| Instant::now() >= until) {
+                    level_lights_until = None;
+                    controller.set_player_light(base_lights(
+                        profiles,
+                        mapper.current(),
+                        battery,
+                    ))?;
                 }
             }
         });
 
         // UI更新スレッド(リフレッシュレートより速い)
-        s.spawn(move || {
-            let mut enigo = Enigo::new();
+        let updater = s.spawn(move || -> Result<()> {
+            let mut output = shared.output(id, haptics);
+
+            // ホイールの最小単位(高解像度ホイールなら1ノッチ未満も送れる)
+            let resolution = output.scroll_resolution();
 
             // ホイール速度
             let mut vlx = 0.0;
@@ -482,47 +580,85 @@ fn monitor(joycon: &mut JoyCon, gyro: f64, stick: f64) -> Result<()> {
             let mut frx = 0.0;
             let mut fry = 0.0;
 
+            // フリックスティック
+            let mut flick = FlickStick::new();
+
+            // 惰性スクロール
+            let mut kinetic = KineticScroll::new();
+
+            // 絶対座標モードで最後に送った位置
+            let mut last_position = None;
+
+            let mut last = Instant::now();
+
             loop {
                 // 割り込み
                 if *_interrupt.lock().unwrap() {
                     break;
                 }
 
-                // ホイール速度の調整(ドリフト防止のため微量のスティックは無視)
-                let uslx = *slx.lock().unwrap();
-                let usly = *sly.lock().unwrap();
-                vlx = (vlx + (if uslx.abs() < 0.2 { 0.0 } else { uslx }) * stick / 16.0) * 0.9;
-                vly = (vly - (if usly.abs() < 0.2 { 0.0 } else { usly }) * stick / 16.0) * 0.9;
+                // 前回からの経過時間(ジャイロの速度から移動量を求める)
+                let now = Instant::now();
+                let dt = (now - last).as_secs_f64();
+                last = now;
+
+                // 感度の段階を反映
+                let stick = stick * *stick_factor.lock().unwrap();
+
+                // プロファイルのデッドゾーン(ドリフト防止)とカーブを適用
+                let settings = &profiles[*current.lock().unwrap()].stick;
+
+                // ホイール速度の調整
+                let usl = settings
+                    .left_response
+                    .apply(vec2(*slx.lock().unwrap(), *sly.lock().unwrap()));
+                let scroll = &profiles[*current.lock().unwrap()].scroll;
+                if scroll.mode == ScrollMode::Kinetic {
+                    // 弾いた後も惰性で続ける(`--stick` と感度の段階はデフォルトで1倍)
+                    let twist = *twist.lock().unwrap();
+                    let delta = kinetic.update(scroll, usl, twist, stick / 2.0, dt);
+                    vlx = delta.x;
+                    vly = delta.y;
+                } else {
+                    vlx = (vlx + usl.x * stick / 16.0) * 0.9;
+                    vly = (vly - usl.y * stick / 16.0) * 0.9;
+                }
 
-                // マウス速度の調整(ドリフト防止のため微量のスティックは無視)
-                let usrx = *srx.lock().unwrap();
-                let usry = *sry.lock().unwrap();
-                vrx = (vrx + (if usrx.abs() < 0.2 { 0.0 } else { usrx }) * stick * 2.0) * 0.9;
-                vry = (vry - (if usry.abs() < 0.2 { 0.0 } else { usry }) * stick * 2.0) * 0.9;
+                // マウス速度の調整
+                let usr = settings
+                    .right_response
+                    .apply(vec2(*srx.lock().unwrap(), *sry.lock().unwrap()));
+                let mut fx = 0.0;
+                if settings.right == RightStickMode::Flick {
+                    // フリックスティック(速度を持たずに直接動かす)
+                    fx = flick.update(settings, usr, dt);
+                    vrx = 0.0;
+                    vry = 0.0;
+                } else {
+                    vrx = (vrx + usr.x * stick * 2.0) * 0.9;
+                    vry = (vry - usr.y * stick * 2.0) * 0.9;
+                }
 
                 // モーション分
-                let radians = rot.lock().unwrap().to_radians();
-                let ugz = *gz.lock().unwrap();
+                let ugx = *gx.lock().unwrap();
                 let ugy = *gy.lock().unwrap();
-                // ドリフト防止のため微量のモーションは無視
-                let nugz = if ugz.abs() < 2.0 { 0.0 } else { ugz };
-                let nugy = if ugy.abs() < 2.0 { 0.0 } else { ugy };
-                let cos = radians.cos();
-                let sin = radians.sin();
-                let mx = (nugz * cos + nugy * sin) * gyro / 8.0;
-                let my = (nugz * sin - nugy * cos) * gyro / 8.0;
+                // クラッチ中は無視
+                let enabled = *gyro_enabled.lock().unwrap();
+                let mx = if enabled { ugx * dt } else { 0.0 };
+                let my = if enabled { ugy * dt } else { 0.0 };
 
                 // 最終的なホイール移動量
                 let dlx = vlx + flx;
                 let dly = vly + fly;
 
-                // 最終的なマウス移動量
-                let drx = vrx + mx + frx;
-                let dry = vry + my + fry;
+                // 最終的なマウス移動量(精密モード中は遅くし、端数で1ピクセル未満の動きも残す)
+                let scale = *precision.lock().unwrap();
+                let drx = (vrx + mx) * scale + fx + frx;
+                let dry = (vry + my) * scale + fry;
 
                 // 端数を持ち越し
-                let rdlx = dlx.round();
-                let rdly = dly.round();
+                let rdlx = (dlx * resolution).round() / resolution;
+                let rdly = (dly * resolution).round() / resolution;
                 flx = dlx - rdlx;
                 fly = dly - rdly;
 
@@ -532,22 +668,34 @@ fn monitor(joycon: &mut JoyCon, gyro: f64, stick: f64) -> Result<()> {
                 fry = dry - rdry;
 
                 // ホイール
-                enigo.mouse_scroll_x(rdlx as i32);
-                enigo.mouse_scroll_y(rdly as i32);
+                output.scroll(rdlx, rdly)?;
 
-                // マウス移動
-                enigo.mouse_move_relative(rdrx as i32, rdry as i32);
+                // マウス移動(絶対座標モードでは位置が変わった時だけ送る)
+                let position = *absolute.lock().unwrap();
+                if let Some(position) = position {
+                    if last_position != Some(position) {
+                        output.move_absolute(position.x, position.y)?;
+                    }
+                    frx = 0.0;
+                    fry = 0.0;
+                } else {
+                    output.move_relative(rdrx as i32, rdry as i32)?;
+                }
+                last_position = position;
 
                 // 5ms毎に実行
                 thread::sleep(Duration::from_millis(5));
             }
+            Ok(())
         });
 
         // センサースレッドが終了(切断等)したら、UIスレッドも落とす。
-        let _ = handler.join().unwrap();
-        let mut _interrupt = interrupt.lock().unwrap();
-        *_interrupt = true;
-    });
-
-    Ok(())
+        let handled = handler.join();
+        *interrupt.lock().unwrap() = true;
+        let updated = updater.join();
+        // パニックは呼び出し元で表示する
+        let handled = handled.unwrap_or_else(|panic| panic::resume_unwind(panic));
+        let updated = updated.unwrap_or_else(|panic| panic::resume_unwind(panic));
+        handled.and(updated)
+    })
 }
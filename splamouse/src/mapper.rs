@@ -0,0 +1,219 @@
+use crate::{
+    gesture::GestureDetector,
+    profile::{Action, Profile},
+};
+use hid_gamepad_types::JoyKey;
+use std::time::Duration;
+
+/// ボタンの押下・解放をプロファイルの動作に変換する
+///
+/// レイヤーとプロファイルの切り替えはここで処理し、出力が必要な動作だけを返す。
+pub struct Mapper<'a> {
+    profiles: &'a [Profile],
+    current: usize,
+    /// 有効なレイヤー(後に押したものが優先)
+    layers: Vec<String>,
+    /// 押している `gyro:off`、`gyro:on` と `precision`
+    held: Vec<Action>,
+    gestures: GestureDetector,
+}
+
+impl<'a> Mapper<'a> {
+    pub fn new(profiles: &'a [Profile]) -> Self {
+        assert!(!profiles.is_empty());
+        Mapper {
+            profiles,
+            current: 0,
+            layers: vec![],
+            held: vec![],
+            gestures: GestureDetector::new(),
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn profile(&self) -> &'a Profile {
+        &self.profiles[self.current]
+    }
+
+    /// ジャイロによるマウス移動を行うか
+    pub fn gyro_enabled(&self) -> bool {
+        if self.held.contains(&Action::GyroOff) {
+            false
+        } else if self.profile().gyro_on_held() {
+            self.held.contains(&Action::GyroOn)
+        } else {
+            true
+        }
+    }
+
+    /// 精密モード(カーソル移動を遅くする)か
+    pub fn precision(&self) -> bool {
+        self.held.contains(&Action::Precision)
+    }
+
+    /// 時刻 `now` のボタンの状態を反映し、出力すべき動作を(動作, 押下か)の順に返す
+    ///
+    /// 長押しなどの判定のため、ボタンが変化しなくても定期的に呼ぶ。
+    pub fn update(
+        &mut self,
+        now: Duration,
+        is_pressed: impl Fn(JoyKey) -> bool,
+    ) -> Vec<(Action, bool)> {
+        let profile = self.profile();
+        let layers = &self.layers;
+        let actions = self.gestures.update(
+            now,
+            &profile.gestures,
+            &profile.chords,
+            is_pressed,
+            |button| profile.binding(button, layers).clone(),
+        );
+
+        let mut out = vec![];
+        for (action, pressed) in actions {
+            match (&action, pressed) {
+                (Action::Layer(name), true) => self.layers.push(name.clone()),
+                (Action::Layer(name), false) => {
+                    if let Some(pos) = self.layers.iter().rposition(|l| l == name) {
+                        self.layers.remove(pos);
+                    }
+                }
+                (Action::NextProfile, true) => self.switch(1),
+                (Action::PrevProfile, true) => self.switch(self.profiles.len() - 1),
+                (Action::GyroOff | Action::GyroOn | Action::Precision, true) => {
+                    self.held.push(action)
+                }
+                (Action::GyroOff | Action::GyroOn | Action::Precision, false) => {
+                    if let Some(pos) = self.held.iter().position(|a| a == &action) {
+                        self.held.remove(pos);
+                    }
+                }
+                (Action::NextProfile | Action::PrevProfile, false) => {}
+                _ => out.push((action, pressed)),
+            }
+        }
+        out
+    }
+
+    fn switch(&mut self, offset: usize) {
+        self.current = (self.current + offset) % self.profiles.len();
+        // レイヤーはプロファイルごとのものなので解除する
+        self.layers.clear();
+    }
+}
+
+#[cfg(test)]
+fn test_profiles() -> Vec<Profile> {
+    vec![
+        Profile::parse(
+            "first",
+            "[buttons]\ncapture = \"layer:fn\"\n[layers.fn]\ne = \"key:ctrl+w\"\nhome = \"profile:next\"\n",
+        )
+        .unwrap(),
+        Profile::parse("second", "[buttons]\ne = \"key:ctrl+tab\"\n").unwrap(),
+    ]
+}
+
+#[test]
+fn layer_and_profile_switch() {
+    use enigo::Key;
+
+    let profiles = test_profiles();
+    let mut mapper = Mapper::new(&profiles);
+    let mut now = Duration::ZERO;
+    let mut tick = || {
+        now += Duration::from_millis(100);
+        now
+    };
+
+    // レイヤーキー自体は何も出力しない
+    assert!(mapper.update(tick(), |b| b == JoyKey::Capture).is_empty());
+    let out = mapper.update(tick(), |b| b == JoyKey::Capture || b == JoyKey::E);
+    assert_eq!(
+        out,
+        vec![(Action::Key(vec![Key::Control, Key::Layout('w')]), true)]
+    );
+    // レイヤーを先に離しても、押した時の動作で離す
+    assert!(mapper.update(tick(), |b| b == JoyKey::E).is_empty());
+    let out = mapper.update(tick(), |_| false);
+    assert_eq!(
+        out,
+        vec![(Action::Key(vec![Key::Control, Key::Layout('w')]), false)]
+    );
+
+    mapper.update(tick(), |b| b == JoyKey::Capture);
+    let home = |b| b == JoyKey::Capture || b == JoyKey::Home;
+    assert!(mapper.update(tick(), home).is_empty());
+    assert_eq!(mapper.current(), 1);
+    mapper.update(tick(), |_| false);
+    let out = mapper.update(tick(), |b| b == JoyKey::E);
+    assert_eq!(out, vec![(Action::Key(vec![Key::Control, Key::Tab]), true)]);
+}
+
+#[test]
+fn gyro_clutch() {
+    let profiles = vec![
+        Profile::parse("off", "[buttons]\nl = \"gyro:off\"\n").unwrap(),
+        Profile::parse("on", "[buttons]\nl = \"gyro:on\"\n").unwrap(),
+    ];
+    let mut mapper = Mapper::new(&profiles);
+    assert!(mapper.gyro_enabled());
+    assert!(mapper.update(Duration::ZERO, |b| b == JoyKey::L).is_empty());
+    assert!(!mapper.gyro_enabled());
+    mapper.update(Duration::ZERO, |_| false);
+    assert!(mapper.gyro_enabled());
+
+    let mut mapper = Mapper::new(&profiles[1..]);
+    assert!(!mapper.gyro_enabled());
+    mapper.update(Duration::ZERO, |b| b == JoyKey::L);
+    assert!(mapper.gyro_enabled());
+
+    let profiles = vec![Profile::parse("precision", "[buttons]\nzl = \"precision\"\n").unwrap()];
+    let mut mapper = Mapper::new(&profiles);
+    assert!(mapper
+        .update(Duration::ZERO, |b| b == JoyKey::ZL)
+        .is_empty());
+    assert!(mapper.precision());
+    mapper.update(Duration::ZERO, |_| false);
+    assert!(!mapper.precision());
+}
+
+#[test]
+fn gestures_in_layers() {
+    use enigo::MouseButton;
+
+    let profiles = vec![Profile::parse(
+        "gestures",
+        "[buttons]\nr = { tap = \"click:right\", hold = \"mouse:right\" }\n\
+         [layers.fn]\nr = { hold = \"gyro:off\" }\n\
+         [chords]\n\"l+zl\" = \"layer:fn\"\n",
+    )
+    .unwrap()];
+    let mut mapper = Mapper::new(&profiles);
+    let ms = Duration::from_millis;
+
+    // 長押しでドラッグ
+    assert!(mapper.update(ms(0), |b| b == JoyKey::R).is_empty());
+    assert_eq!(
+        mapper.update(ms(400), |b| b == JoyKey::R),
+        vec![(Action::Mouse(MouseButton::Right), true)]
+    );
+    mapper.update(ms(500), |_| false);
+
+    // 同時押しでレイヤーを有効にすると、長押しでジャイロが止まる
+    let fn_held = |b| b == JoyKey::L || b == JoyKey::ZL;
+    assert!(mapper.update(ms(1000), fn_held).is_empty());
+    assert!(mapper
+        .update(ms(1100), |b| fn_held(b) || b == JoyKey::R)
+        .is_empty());
+    assert!(mapper.gyro_enabled());
+    assert!(mapper
+        .update(ms(1500), |b| fn_held(b) || b == JoyKey::R)
+        .is_empty());
+    assert!(!mapper.gyro_enabled());
+    assert!(mapper.update(ms(1600), |_| false).is_empty());
+    assert!(mapper.gyro_enabled());
+}
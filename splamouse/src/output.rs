@@ -0,0 +1,492 @@
+use crate::{
+    keys::platform_key,
+    pointer::Desktop,
+    profile::{Action, Step},
+    timer::Timer,
+};
+use anyhow::Result;
+use clap::ArgEnum;
+use enigo::{Enigo, Key, KeyboardControllable, MouseButton, MouseControllable};
+use serde::{Serialize, Serializer};
+use std::{
+    fmt::Debug,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// キーボード・マウスの出力先
+pub trait Output {
+    fn key_down(&mut self, key: Key) -> Result<()>;
+    fn key_up(&mut self, key: Key) -> Result<()>;
+    fn mouse_down(&mut self, button: MouseButton) -> Result<()>;
+    fn mouse_up(&mut self, button: MouseButton) -> Result<()>;
+
+    fn mouse_click(&mut self, button: MouseButton) -> Result<()> {
+        self.mouse_down(button)?;
+        self.mouse_up(button)
+    }
+
+    /// ホイールを回す(ノッチ単位、右・下が正)
+    fn scroll(&mut self, x: f64, y: f64) -> Result<()>;
+
+    /// 1ノッチを何分割して送れるか
+    fn scroll_resolution(&self) -> f64 {
+        1.0
+    }
+
+    fn move_relative(&mut self, x: i32, y: i32) -> Result<()>;
+    /// 画面上の位置(px、複数のモニターを並べた座標)にカーソルを移動する
+    fn move_absolute(&mut self, x: i32, y: i32) -> Result<()>;
+
+    /// 文字列を入力する(標準では一文字ずつキーを押して離す)
+    fn text(&mut self, text: &str) -> Result<()> {
+        for c in text.chars() {
+            self.key_down(Key::Layout(c))?;
+            self.key_up(Key::Layout(c))?;
+        }
+        Ok(())
+    }
+
+    /// キー入力の後に空ける間隔(出力先が順番を保証しない場合)
+    fn key_gap(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn apply(&mut self, event: Event) -> Result<()> {
+        match event {
+            Event::KeyDown { key } => self.key_down(key),
+            Event::KeyUp { key } => self.key_up(key),
+            Event::MouseDown { button } => self.mouse_down(button),
+            Event::MouseUp { button } => self.mouse_up(button),
+            Event::Scroll { x, y } => self.scroll(x, y),
+            Event::Move { x, y } => self.move_relative(x, y),
+            Event::MoveTo { x, y } => self.move_absolute(x, y),
+            Event::Text { text } => self.text(&text),
+        }
+    }
+}
+
+/// 起動時に選ぶ出力方法
+#[derive(ArgEnum, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Backend {
+    /// OSのAPIで入力を送る(LinuxではX11のXTest)
+    Enigo,
+    /// Linuxの仮想入力デバイスを作る(Waylandでも動き、ホイールが滑らか)
+    #[cfg(target_os = "linux")]
+    Uinput,
+    /// 実際には操作せず、標準出力にJSON Linesで書き出す
+    Log,
+}
+
+impl Backend {
+    /// 出力先を開く。出力は各スレッドで開く。
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub fn open(self, desktop: &Desktop) -> Result<Box<dyn Output>> {
+        Ok(match self {
+            Backend::Enigo => Box::new(Enigo::new()),
+            #[cfg(target_os = "linux")]
+            Backend::Uinput => Box::new(crate::uinput::Uinput::new(desktop)?),
+            Backend::Log => Box::new(Log::new(io::stdout())),
+        })
+    }
+}
+
+impl Output for Enigo {
+    fn key_down(&mut self, key: Key) -> Result<()> {
+        KeyboardControllable::key_down(self, platform_key(key));
+        Ok(())
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<()> {
+        KeyboardControllable::key_up(self, platform_key(key));
+        Ok(())
+    }
+
+    fn mouse_down(&mut self, button: MouseButton) -> Result<()> {
+        MouseControllable::mouse_down(self, button);
+        Ok(())
+    }
+
+    fn mouse_up(&mut self, button: MouseButton) -> Result<()> {
+        MouseControllable::mouse_up(self, button);
+        Ok(())
+    }
+
+    fn mouse_click(&mut self, button: MouseButton) -> Result<()> {
+        MouseControllable::mouse_click(self, button);
+        Ok(())
+    }
+
+    fn scroll(&mut self, x: f64, y: f64) -> Result<()> {
+        self.mouse_scroll_x(x.round() as i32);
+        self.mouse_scroll_y(y.round() as i32);
+        Ok(())
+    }
+
+    fn move_relative(&mut self, x: i32, y: i32) -> Result<()> {
+        self.mouse_move_relative(x, y);
+        Ok(())
+    }
+
+    fn move_absolute(&mut self, x: i32, y: i32) -> Result<()> {
+        self.mouse_move_to(x, y);
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.key_sequence(text);
+        Ok(())
+    }
+
+    fn key_gap(&self) -> Duration {
+        // 間隔を空けないと、マシンスペックによって順番が前後してしまう。
+        Duration::from_millis(10)
+    }
+}
+
+/// プロファイルの動作をボタンの押下・解放に合わせて実行する
+///
+/// キーの組み合わせは修飾キーを先に押し、逆順に離す。
+/// マクロは押した時に最初の待ち時間までの手順を送り、残りは `timer` で待ってから送る
+/// (待っている間も他の出力は止まらない)。
+pub fn perform(
+    output: &mut dyn Output,
+    timer: &Timer,
+    action: &Action,
+    pressed: bool,
+) -> Result<()> {
+    match (action, pressed) {
+        (Action::Macro(steps), true) => {
+            if let Some((delay, rest)) = perform_steps(output, steps)? {
+                timer.schedule(delay, rest)?;
+            }
+            Ok(())
+        }
+        _ => perform_action(output, action, pressed),
+    }
+}
+
+/// マクロ以外の動作を実行する
+fn perform_action(output: &mut dyn Output, action: &Action, pressed: bool) -> Result<()> {
+    match (action, pressed) {
+        (Action::Key(keys), true) => {
+            for key in modifiers_first(keys) {
+                output.key_down(key)?;
+            }
+        }
+        (Action::Key(keys), false) => {
+            for key in modifiers_first(keys).into_iter().rev() {
+                output.key_up(key)?;
+            }
+        }
+        (Action::Mouse(mouse), true) => output.mouse_down(*mouse)?,
+        (Action::Mouse(mouse), false) => output.mouse_up(*mouse)?,
+        (Action::Click(mouse), false) => output.mouse_click(*mouse)?,
+        (Action::Scroll { x, y }, true) => output.scroll(*x as f64, *y as f64)?,
+        (Action::Text(text), true) => output.text(text)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// マクロの手順を最初の待ち時間まで実行し、残りがあれば(待ち時間, 残りの手順)を返す
+pub fn perform_steps(
+    output: &mut dyn Output,
+    steps: &[Step],
+) -> Result<Option<(Duration, Vec<Step>)>> {
+    for (i, step) in steps.iter().enumerate() {
+        match step {
+            Step::Action(action) => {
+                perform_action(output, action, true)?;
+                perform_action(output, action, false)?;
+            }
+            Step::Wait(ms) => {
+                return Ok(Some((Duration::from_millis(*ms), steps[i + 1..].to_vec())));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn modifiers_first(keys: &[Key]) -> Vec<Key> {
+    let mut keys = keys.to_vec();
+    keys.sort_by_key(|key| !matches!(key, Key::Meta | Key::Control | Key::Shift | Key::Alt));
+    keys
+}
+
+/// 出力の記録・ログ用のイベント
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    KeyDown {
+        #[serde(serialize_with = "serialize_debug")]
+        key: Key,
+    },
+    KeyUp {
+        #[serde(serialize_with = "serialize_debug")]
+        key: Key,
+    },
+    MouseDown {
+        #[serde(serialize_with = "serialize_debug")]
+        button: MouseButton,
+    },
+    MouseUp {
+        #[serde(serialize_with = "serialize_debug")]
+        button: MouseButton,
+    },
+    Scroll {
+        x: f64,
+        y: f64,
+    },
+    Move {
+        x: i32,
+        y: i32,
+    },
+    MoveTo {
+        x: i32,
+        y: i32,
+    },
+    Text {
+        text: String,
+    },
+}
+
+fn serialize_debug<T: Debug, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!("{:?}", value))
+}
+
+/// イベントを一つずつ受け取る出力(移動のない操作は送らない)
+pub trait EventSink {
+    fn event(&mut self, event: Event) -> Result<()>;
+
+    fn scroll_resolution(&self) -> f64 {
+        1.0
+    }
+}
+
+impl<T: EventSink> Output for T {
+    fn key_down(&mut self, key: Key) -> Result<()> {
+        self.event(Event::KeyDown { key })
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<()> {
+        self.event(Event::KeyUp { key })
+    }
+
+    fn mouse_down(&mut self, button: MouseButton) -> Result<()> {
+        self.event(Event::MouseDown { button })
+    }
+
+    fn mouse_up(&mut self, button: MouseButton) -> Result<()> {
+        self.event(Event::MouseUp { button })
+    }
+
+    fn scroll(&mut self, x: f64, y: f64) -> Result<()> {
+        if x == 0.0 && y == 0.0 {
+            return Ok(());
+        }
+        self.event(Event::Scroll { x, y })
+    }
+
+    fn scroll_resolution(&self) -> f64 {
+        EventSink::scroll_resolution(self)
+    }
+
+    fn move_relative(&mut self, x: i32, y: i32) -> Result<()> {
+        if x == 0 && y == 0 {
+            return Ok(());
+        }
+        self.event(Event::Move { x, y })
+    }
+
+    fn move_absolute(&mut self, x: i32, y: i32) -> Result<()> {
+        self.event(Event::MoveTo { x, y })
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        self.event(Event::Text {
+            text: text.to_string(),
+        })
+    }
+}
+
+/// イベントを一行ずつJSONで書き出す
+pub struct Log<W: Write> {
+    out: W,
+}
+
+impl<W: Write> Log<W> {
+    pub fn new(out: W) -> Self {
+        Log { out }
+    }
+}
+
+impl<W: Write> EventSink for Log<W> {
+    fn event(&mut self, event: Event) -> Result<()> {
+        serde_json::to_writer(&mut self.out, &event)?;
+        writeln!(self.out)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// イベントをメモリに記録する(テスト用)
+///
+/// 複製したものは同じ記録を共有する。
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub struct Recording {
+    events: Arc<Mutex<Vec<Event>>>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Recording {
+    pub fn new() -> Self {
+        Recording::default()
+    }
+
+    /// 記録したイベントを取り出す
+    pub fn take(&self) -> Vec<Event> {
+        std::mem::take(&mut self.events.lock().unwrap())
+    }
+}
+
+impl EventSink for Recording {
+    fn event(&mut self, event: Event) -> Result<()> {
+        self.events.lock().unwrap().push(event);
+        Ok(())
+    }
+}
+
+#[test]
+fn perform_default_profile() {
+    use crate::mapper::Mapper;
+    use hid_gamepad_types::JoyKey;
+
+    let profiles = [crate::profile::Profile::default()];
+    let mut mapper = Mapper::new(&profiles);
+    let mut recording = Recording::new();
+    let timer = Timer::spawn(|| Box::new(Recording::new()));
+    let mut press = |pressed: &[JoyKey]| {
+        for (action, down) in mapper.update(Duration::ZERO, |b| pressed.contains(&b)) {
+            perform(&mut recording, &timer, &action, down).unwrap();
+        }
+    };
+
+    press(&[JoyKey::Minus]);
+    press(&[]);
+    press(&[JoyKey::ZR]);
+    press(&[]);
+    press(&[JoyKey::Left]);
+    // タブを閉じるショートカットはOSごとに違う
+    let primary = if cfg!(target_os = "macos") {
+        Key::Meta
+    } else {
+        Key::Control
+    };
+    let w = Key::Layout('w');
+    assert_eq!(
+        recording.take(),
+        vec![
+            Event::KeyDown { key: primary },
+            Event::KeyDown { key: w },
+            Event::KeyUp { key: w },
+            Event::KeyUp { key: primary },
+            Event::MouseDown {
+                button: MouseButton::Left
+            },
+            Event::MouseUp {
+                button: MouseButton::Left
+            },
+            Event::KeyDown { key: Key::Control },
+            Event::KeyDown { key: Key::Shift },
+            Event::KeyDown { key: Key::Tab },
+            Event::KeyUp { key: Key::Tab },
+            Event::KeyUp { key: Key::Shift },
+            Event::KeyUp { key: Key::Control },
+        ]
+    );
+
+    // 修飾キーを後に書いても先に押す
+    let action = "key:w+ctrl".parse().unwrap();
+    perform(&mut recording, &timer, &action, true).unwrap();
+    perform(&mut recording, &timer, &action, false).unwrap();
+    assert_eq!(
+        recording.take(),
+        vec![
+            Event::KeyDown { key: Key::Control },
+            Event::KeyDown {
+                key: Key::Layout('w')
+            },
+            Event::KeyUp {
+                key: Key::Layout('w')
+            },
+            Event::KeyUp { key: Key::Control },
+        ]
+    );
+}
+
+#[test]
+fn log_json_lines() {
+    let mut log = Log::new(vec![]);
+    log.key_down(Key::Layout('w')).unwrap();
+    log.move_relative(0, 0).unwrap();
+    log.scroll(0.0, -0.5).unwrap();
+    assert_eq!(
+        String::from_utf8(log.out).unwrap(),
+        "{\"event\":\"key_down\",\"key\":\"Layout('w')\"}\n{\"event\":\"scroll\",\"x\":0.0,\"y\":-0.5}\n"
+    );
+}
+
+#[test]
+fn perform_macro() {
+    use crate::queue::Queue;
+    use std::{collections::HashMap, thread};
+
+    let action: Action = toml::from_str::<HashMap<String, Action>>(
+        "m = [\"key:ctrl+a\", \"wait:50\", \"text:Hi\", \"click:left\"]",
+    )
+    .unwrap()
+    .remove("m")
+    .unwrap();
+    let recording = Recording::new();
+    let sink = recording.clone();
+    let queue = Queue::spawn(move || Ok(Box::new(sink))).unwrap();
+    let timer = Timer::spawn({
+        let queue = queue.clone();
+        move || Box::new(queue)
+    });
+
+    // 待っている間も出力スレッドは止まらず、カーソルの移動が先に出る
+    let mut output = queue.clone();
+    perform(&mut output, &timer, &action, true).unwrap();
+    perform(&mut output, &timer, &action, false).unwrap();
+    output.move_relative(3, 0).unwrap();
+    queue.flush().unwrap();
+    thread::sleep(Duration::from_millis(200));
+    queue.flush().unwrap();
+    assert_eq!(
+        recording.take(),
+        vec![
+            Event::KeyDown { key: Key::Control },
+            Event::KeyDown {
+                key: Key::Layout('a')
+            },
+            Event::KeyUp {
+                key: Key::Layout('a')
+            },
+            Event::KeyUp { key: Key::Control },
+            Event::Move { x: 3, y: 0 },
+            Event::Text {
+                text: "Hi".to_string()
+            },
+            Event::MouseDown {
+                button: MouseButton::Left
+            },
+            Event::MouseUp {
+                button: MouseButton::Left
+            },
+        ]
+    );
+}
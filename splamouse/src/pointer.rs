@@ -0,0 +1,381 @@
+use anyhow::{bail, Context, Result};
+use cgmath::{vec2, vec3, InnerSpace, Quaternion, Rotation, Vector2};
+use serde::{de, Deserialize, Deserializer};
+use std::{fmt, str::FromStr};
+
+/// ジャイロでカーソルを動かす方法
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum PointerMode {
+    /// 回した分だけカーソルを動かす
+    #[default]
+    Relative,
+    /// コントローラーの向きをそのまま画面上の位置にする(Wii リモコンのポインター)
+    Absolute,
+}
+
+impl PointerMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            PointerMode::Relative => "relative",
+            PointerMode::Absolute => "absolute",
+        }
+    }
+}
+
+impl fmt::Display for PointerMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for PointerMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "relative" => Ok(PointerMode::Relative),
+            "absolute" => Ok(PointerMode::Absolute),
+            _ => bail!(
+                "unknown pointer mode `{}` (expected `relative` or `absolute`)",
+                s
+            ),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PointerMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// プロファイルの `[pointer]` の設定
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PointerSettings {
+    pub mode: PointerMode,
+    /// 最初のモニターの左端から右端までを指す角度(deg)
+    pub fov: f64,
+    /// 手ぶれを抑えるために位置を均す時間(s)。0で無効。
+    pub smooth_time: f64,
+}
+
+impl PointerSettings {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.fov > 0.0 && self.fov < 180.0) {
+            bail!("pointer fov must be between 0 and 180 (got {})", self.fov);
+        }
+        if self.smooth_time < 0.0 {
+            bail!("pointer smooth_time must not be negative");
+        }
+        Ok(())
+    }
+}
+
+impl Default for PointerSettings {
+    fn default() -> Self {
+        PointerSettings {
+            mode: PointerMode::default(),
+            fov: 40.0,
+            smooth_time: 0.05,
+        }
+    }
+}
+
+/// モニターの位置と大きさ(px)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Monitor {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Monitor {
+    pub fn center(&self) -> Vector2<f64> {
+        vec2(
+            self.x as f64 + self.width as f64 / 2.0,
+            self.y as f64 + self.height as f64 / 2.0,
+        )
+    }
+
+    /// モニター内で `point` に最も近い点
+    fn clamp(&self, point: Vector2<f64>) -> Vector2<f64> {
+        vec2(
+            point
+                .x
+                .clamp(self.x as f64, (self.x + self.width as i32 - 1) as f64),
+            point
+                .y
+                .clamp(self.y as f64, (self.y + self.height as i32 - 1) as f64),
+        )
+    }
+}
+
+/// xrandr と同じ `1920x1080+0+0` の形式(オフセットは省略可)
+impl FromStr for Monitor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = || -> Option<Monitor> {
+            let (width, rest) = s.trim().split_once('x')?;
+            let (height, offset) = rest.split_at(rest.find(['+', '-']).unwrap_or(rest.len()));
+            let (x, y) = if offset.is_empty() {
+                (0, 0)
+            } else {
+                let (x, rest) = signed_number(offset)?;
+                let (y, rest) = signed_number(rest)?;
+                if !rest.is_empty() {
+                    return None;
+                }
+                (x, y)
+            };
+            Some(Monitor {
+                x,
+                y,
+                width: width.parse().ok().filter(|&w| w > 0)?,
+                height: height.parse().ok().filter(|&h| h > 0)?,
+            })
+        };
+        parse().with_context(|| format!("invalid monitor `{}` (e.g. `1920x1080+0+0`)", s))
+    }
+}
+
+/// 符号付きの数を一つ読み、残りを返す(`+-1920` も負の数として読む)
+fn signed_number(s: &str) -> Option<(i32, &str)> {
+    let s = s.strip_prefix('+').unwrap_or(s);
+    let end = s.get(1..)?.find(['+', '-']).map_or(s.len(), |i| i + 1);
+    Some((s[..end].parse().ok()?, &s[end..]))
+}
+
+/// デスクトップを構成するモニター(最初のものを中心にする)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Desktop {
+    pub monitors: Vec<Monitor>,
+}
+
+impl Desktop {
+    pub fn new(monitors: Vec<Monitor>) -> Result<Self> {
+        if monitors.is_empty() {
+            bail!("at least one monitor is needed");
+        }
+        Ok(Desktop { monitors })
+    }
+
+    /// OSから分かればメインのモニター、分からなければ 1920x1080
+    pub fn detect() -> Self {
+        #[cfg(any(target_os = "windows", target_os = "macos"))]
+        let (width, height) = enigo::Enigo::main_display_size();
+        #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+        let (width, height) = (1920, 1080);
+        Desktop {
+            monitors: vec![Monitor {
+                x: 0,
+                y: 0,
+                width: width as u32,
+                height: height as u32,
+            }],
+        }
+    }
+
+    pub fn primary(&self) -> &Monitor {
+        &self.monitors[0]
+    }
+
+    /// 全てのモニターを囲む範囲(左上, 右下)
+    pub fn bounds(&self) -> (Vector2<i32>, Vector2<i32>) {
+        let min = |f: fn(&Monitor) -> i32| self.monitors.iter().map(f).min().unwrap();
+        let max = |f: fn(&Monitor) -> i32| self.monitors.iter().map(f).max().unwrap();
+        (
+            vec2(min(|m| m.x), min(|m| m.y)),
+            vec2(
+                max(|m| m.x + m.width as i32 - 1),
+                max(|m| m.y + m.height as i32 - 1),
+            ),
+        )
+    }
+
+    /// いずれかのモニター内で `point` に最も近い点(モニターの隙間に入らないようにする)
+    fn clamp(&self, point: Vector2<f64>) -> Vector2<f64> {
+        self.monitors
+            .iter()
+            .map(|monitor| monitor.clamp(point))
+            .min_by(|a, b| {
+                (a - point)
+                    .magnitude2()
+                    .partial_cmp(&(b - point).magnitude2())
+                    .unwrap()
+            })
+            .unwrap()
+    }
+}
+
+/// コントローラーの向きから画面上の位置を求める
+///
+/// 向きの推定は左右の回転がずれていくので、`recenter` で正面を合わせ直す。
+#[derive(Debug, Default)]
+pub struct AbsolutePointer {
+    /// 最初のモニターの中心に対応する向き(ヨー, ピッチ)。次の更新で決める場合は `None`。
+    center: Option<(f64, f64)>,
+    position: Option<Vector2<f64>>,
+}
+
+impl AbsolutePointer {
+    /// 角度の差をこれまでに制限する(真横を向いても発散しないように)
+    const MAX_ANGLE: f64 = 85.0;
+
+    pub fn new() -> Self {
+        AbsolutePointer::default()
+    }
+
+    /// 次の更新時の向きを最初のモニターの中心にする
+    pub fn recenter(&mut self) {
+        self.center = None;
+        self.position = None;
+    }
+
+    /// コントローラーの向き(コントローラーから世界への回転、SDLの軸)から、
+    /// `dt` 秒後のカーソルの位置を求める
+    pub fn update(
+        &mut self,
+        settings: &PointerSettings,
+        desktop: &Desktop,
+        orientation: Quaternion<f64>,
+        dt: f64,
+    ) -> Vector2<i32> {
+        // コントローラーの先端(-Z)が指す向き
+        let forward = orientation.rotate_vector(vec3(0.0, 0.0, -1.0));
+        let yaw = forward.x.atan2(-forward.z);
+        let pitch = forward.y.clamp(-1.0, 1.0).asin();
+        let (center_yaw, center_pitch) = *self.center.get_or_insert((yaw, pitch));
+
+        let limit = Self::MAX_ANGLE.to_radians();
+        let angle = |a: f64| a.sin().atan2(a.cos()).clamp(-limit, limit);
+        let yaw = angle(yaw - center_yaw);
+        let pitch = angle(pitch - center_pitch);
+
+        // 平らな画面を指すように、角度の正接に比例させる
+        let primary = desktop.primary();
+        let scale = primary.width as f64 / 2.0 / (settings.fov.to_radians() / 2.0).tan();
+        let target = desktop.clamp(primary.center() + vec2(yaw.tan(), -pitch.tan()) * scale);
+
+        let position = match self.position {
+            Some(position) if settings.smooth_time > 0.0 => {
+                position + (target - position) * (1.0 - (-dt / settings.smooth_time).exp())
+            }
+            _ => target,
+        };
+        self.position = Some(position);
+        position.map(|p| p.round() as i32)
+    }
+}
+
+#[test]
+fn parse_monitors() {
+    assert_eq!(
+        "2560x1440+1920+0".parse::<Monitor>().unwrap(),
+        Monitor {
+            x: 1920,
+            y: 0,
+            width: 2560,
+            height: 1440,
+        }
+    );
+    assert_eq!(
+        "1920x1080-1920+-200".parse::<Monitor>().unwrap(),
+        Monitor {
+            x: -1920,
+            y: -200,
+            width: 1920,
+            height: 1080,
+        }
+    );
+    assert_eq!(
+        "800x600".parse::<Monitor>().unwrap().center(),
+        vec2(400.0, 300.0)
+    );
+    assert!("1920x".parse::<Monitor>().is_err());
+    assert!("0x1080+0+0".parse::<Monitor>().is_err());
+}
+
+#[test]
+fn point_at_monitors() {
+    use cgmath::{Deg, Rotation3};
+
+    let settings = PointerSettings {
+        mode: PointerMode::Absolute,
+        fov: 90.0,
+        smooth_time: 0.0,
+    };
+    // 右に背の低いモニターを並べ、その上下は隙間
+    let desktop = Desktop::new(vec![
+        "1000x1000+0+0".parse().unwrap(),
+        "1000x500+1000+250".parse().unwrap(),
+    ])
+    .unwrap();
+    assert_eq!(desktop.bounds(), (vec2(0, 0), vec2(1999, 999)));
+
+    let turn = |yaw: f64, pitch: f64| {
+        // 右・上に向けるのが正
+        Quaternion::from_angle_y(Deg(-yaw)) * Quaternion::from_angle_x(Deg(pitch))
+    };
+    let mut pointer = AbsolutePointer::new();
+    // 最初の向きが中心になる
+    assert_eq!(
+        pointer.update(&settings, &desktop, turn(30.0, 0.0), 0.01),
+        vec2(500, 500)
+    );
+    assert_eq!(
+        pointer.update(&settings, &desktop, turn(75.0, 0.0), 0.01),
+        vec2(1000, 500)
+    );
+    assert_eq!(
+        pointer.update(&settings, &desktop, turn(30.0, 20.0), 0.01),
+        vec2(500, 318)
+    );
+    // 隙間を指すと近いモニターの端に留まる
+    assert_eq!(
+        pointer.update(&settings, &desktop, turn(80.0, 30.0), 0.01),
+        vec2(1096, 250)
+    );
+    assert_eq!(
+        pointer.update(&settings, &desktop, turn(-60.0, -60.0), 0.01),
+        vec2(0, 999)
+    );
+
+    pointer.recenter();
+    pointer.update(&settings, &desktop, turn(-60.0, -60.0), 0.01);
+    assert_eq!(
+        pointer.update(&settings, &desktop, turn(-15.0, -60.0), 0.01),
+        vec2(1000, 500)
+    );
+}
+
+#[test]
+fn smooth_position() {
+    let settings = PointerSettings {
+        mode: PointerMode::Absolute,
+        fov: 90.0,
+        smooth_time: 0.1,
+    };
+    let desktop = Desktop::new(vec!["1000x1000".parse().unwrap()]).unwrap();
+    let mut pointer = AbsolutePointer::new();
+    pointer.update(
+        &settings,
+        &desktop,
+        Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        0.01,
+    );
+    let right = Quaternion::from_sv(
+        (22.5f64).to_radians().cos(),
+        vec3(0.0, -(22.5f64).to_radians().sin(), 0.0),
+    );
+    let x = pointer.update(&settings, &desktop, right, 0.1).x;
+    assert!(x > 500 && x < 999, "{}", x);
+    for _ in 0..100 {
+        pointer.update(&settings, &desktop, right, 0.1);
+    }
+    assert_eq!(pointer.update(&settings, &desktop, right, 0.1).x, 999);
+}
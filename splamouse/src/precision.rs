@@ -0,0 +1,72 @@
+use crate::gyro::GyroSettings;
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+/// プロファイルの `[precision]` の設定
+///
+/// `precision` を割り当てたボタンを押している間、ジャイロとスティックによるカーソル移動を
+/// `scale` 倍にし、ジャイロの手ぶれをより強く抑える。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrecisionSettings {
+    /// カーソル移動の倍率(0〜1)
+    pub scale: f64,
+    /// この角速度(deg/s)以下の入力を平均する(`[gyro]` より小さければそちらを使う)
+    pub smooth_threshold: f64,
+    /// 平均する期間(s、`[gyro]` より短ければそちらを使う)
+    pub smooth_time: f64,
+}
+
+impl PrecisionSettings {
+    pub fn validate(&self) -> Result<()> {
+        if !(self.scale > 0.0 && self.scale <= 1.0) {
+            bail!(
+                "precision scale must be between 0 and 1 (got {})",
+                self.scale
+            );
+        }
+        if self.smooth_threshold < 0.0 || self.smooth_time < 0.0 {
+            bail!("precision smoothing must not be negative");
+        }
+        Ok(())
+    }
+
+    /// 精密モード中のジャイロの設定(スムージングだけを強める)
+    pub fn gyro(&self, gyro: &GyroSettings) -> GyroSettings {
+        GyroSettings {
+            smooth_threshold: gyro.smooth_threshold.max(self.smooth_threshold),
+            smooth_time: gyro.smooth_time.max(self.smooth_time),
+            ..gyro.clone()
+        }
+    }
+}
+
+impl Default for PrecisionSettings {
+    fn default() -> Self {
+        PrecisionSettings {
+            scale: 0.3,
+            smooth_threshold: 20.0,
+            smooth_time: 0.25,
+        }
+    }
+}
+
+#[test]
+fn stronger_smoothing() {
+    let precision = PrecisionSettings::default();
+    let gyro = GyroSettings {
+        smooth_threshold: 40.0,
+        ..GyroSettings::default()
+    };
+    let gyro = precision.gyro(&gyro);
+    assert_eq!(gyro.smooth_threshold, 40.0);
+    assert_eq!(gyro.smooth_time, 0.25);
+    assert_eq!(gyro.min_sens, GyroSettings::default().min_sens);
+
+    assert!(PrecisionSettings {
+        scale: 0.0,
+        ..precision
+    }
+    .validate()
+    .is_err());
+}
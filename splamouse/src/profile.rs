@@ -0,0 +1,786 @@
+use crate::{
+    gesture::{Binding, Chord, GestureSettings},
+    gyro::GyroSettings,
+    haptics::HapticSettings,
+    pointer::PointerSettings,
+    precision::PrecisionSettings,
+    scroll::ScrollSettings,
+    sensitivity::Target,
+    stick::StickSettings,
+};
+use anyhow::{bail, Context, Result};
+use enigo::{Key, MouseButton};
+use hid_gamepad_types::JoyKey;
+use serde::{de, Deserialize, Deserializer};
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+/// 組み込みのプロファイル(従来の割り当てと同じ)
+const DEFAULT_PROFILE: &str = include_str!("../profiles/default.toml");
+
+/// OSごとのショートカットの割り当て(組み込みのプロファイルに重ねる)
+#[cfg(target_os = "macos")]
+const PLATFORM_PROFILE: &str = include_str!("../profiles/macos.toml");
+#[cfg(target_os = "windows")]
+const PLATFORM_PROFILE: &str = include_str!("../profiles/windows.toml");
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const PLATFORM_PROFILE: &str = include_str!("../profiles/linux.toml");
+
+/// ボタンに割り当てる動作
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    None,
+    /// 押している間、キーを押し続ける(押した順に押し、逆順に離す)
+    Key(Vec<Key>),
+    /// 押している間、マウスボタンを押し続ける
+    Mouse(MouseButton),
+    /// 離した時にクリックする
+    Click(MouseButton),
+    /// 押した時にホイールを回す
+    Scroll {
+        x: i32,
+        y: i32,
+    },
+    /// 押している間、レイヤーを有効にする
+    Layer(String),
+    /// 押した時に次のプロファイルに切り替える
+    NextProfile,
+    /// 押した時に前のプロファイルに切り替える
+    PrevProfile,
+    /// 押している間、ジャイロによるマウス移動を止める(マウスを持ち上げるのと同じ)
+    GyroOff,
+    /// 押している間だけ、ジャイロによるマウス移動を有効にする
+    GyroOn,
+    /// 押している間、カーソル移動を遅くして細かく狙えるようにする
+    Precision,
+    /// 押した時にコントローラーの今の向きを画面の中心にする
+    Recenter,
+    /// 押した時にジャイロ・スティックの感度を `step` 段階変える
+    Sensitivity {
+        target: Target,
+        step: i32,
+    },
+    /// 押した時に文字列を入力する
+    Text(String),
+    /// 押した時に手順を順番に実行する
+    Macro(Vec<Step>),
+}
+
+/// マクロの一手順
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// キー・クリック・ホイール・文字列(押して離す)
+    Action(Action),
+    /// 次の手順まで待つ(ms)
+    Wait(u64),
+}
+
+impl FromStr for Step {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(ms) = s.trim().strip_prefix("wait:") {
+            let ms = ms
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid wait `{}` (expected milliseconds)", s))?;
+            return Ok(Step::Wait(ms));
+        }
+        match s.parse()? {
+            action @ (Action::Key(_)
+            | Action::Click(_)
+            | Action::Scroll { .. }
+            | Action::Text(_)) => Ok(Step::Action(action)),
+            _ => bail!(
+                "`{}` cannot be used in a macro (expected `key:`, `text:`, `click:`, `scroll:` or `wait:`)",
+                s
+            ),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (kind, arg) = match s.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (s, None),
+        };
+        let action = match (kind.trim().to_ascii_lowercase().as_str(), arg) {
+            ("none", None) => Action::None,
+            ("precision", None) => Action::Precision,
+            ("recenter", None) => Action::Recenter,
+            ("key", Some(chord)) => Action::Key(parse_chord(chord)?),
+            ("mouse", Some(button)) => Action::Mouse(parse_mouse_button(button)?),
+            ("click", Some(button)) => Action::Click(parse_mouse_button(button)?),
+            ("scroll", Some(arg)) => parse_scroll(arg)?,
+            ("layer", Some(name)) if !name.trim().is_empty() => {
+                Action::Layer(name.trim().to_string())
+            }
+            ("profile", Some(arg)) => match arg.trim().to_ascii_lowercase().as_str() {
+                "next" => Action::NextProfile,
+                "prev" => Action::PrevProfile,
+                _ => bail!(
+                    "unknown profile switch `{}` (expected `profile:next` or `profile:prev`)",
+                    s
+                ),
+            },
+            ("gyro", Some(arg)) => match arg.trim().to_ascii_lowercase().as_str() {
+                "off" => Action::GyroOff,
+                "on" => Action::GyroOn,
+                _ => bail!("unknown gyro mode `{}` (expected `gyro:off` or `gyro:on`)", s),
+            },
+            ("sens", Some(arg)) => parse_sensitivity(arg)?,
+            // 空白も入力できるように、前後を削らない
+            ("text", Some(text)) if !text.is_empty() => Action::Text(text.to_string()),
+            ("none" | "precision" | "recenter", Some(_)) => bail!("`{}` takes no argument", kind.trim()),
+            (
+                "key" | "mouse" | "click" | "scroll" | "layer" | "profile" | "gyro" | "sens"
+                | "text",
+                _,
+            ) => {
+                bail!("missing argument in `{}` (e.g. `{}:...`)", s, kind)
+            }
+            _ => bail!(
+                "unknown action `{}` (expected `none`, `precision`, `recenter`, `key:`, `mouse:`, `click:`, `scroll:`, `layer:`, `profile:`, `gyro:`, `sens:` or `text:`)",
+                s
+            ),
+        };
+        Ok(action)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ActionValue {
+    Single(String),
+    Macro(Vec<String>),
+}
+
+/// 文字列なら一つの動作、配列ならマクロ
+impl<'de> Deserialize<'de> for Action {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match ActionValue::deserialize(deserializer)? {
+            ActionValue::Single(s) => s.parse().map_err(de::Error::custom),
+            ActionValue::Macro(steps) if steps.is_empty() => {
+                Err(de::Error::custom("a macro needs at least one step"))
+            }
+            ActionValue::Macro(steps) => steps
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_>>()
+                .map(Action::Macro)
+                .map_err(de::Error::custom),
+        }
+    }
+}
+
+fn parse_chord(chord: &str) -> Result<Vec<Key>> {
+    chord.split('+').map(parse_key).collect()
+}
+
+fn parse_key(name: &str) -> Result<Key> {
+    let name = name.trim();
+    let lower = name.to_ascii_lowercase();
+    let key = match lower.as_str() {
+        "meta" | "cmd" | "command" | "super" | "win" => Key::Meta,
+        "ctrl" | "control" => Key::Control,
+        "shift" => Key::Shift,
+        "alt" | "option" | "opt" => Key::Alt,
+        "tab" => Key::Tab,
+        "return" | "enter" => Key::Return,
+        "escape" | "esc" => Key::Escape,
+        "space" => Key::Space,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "capslock" => Key::CapsLock,
+        "up" => Key::UpArrow,
+        "down" => Key::DownArrow,
+        "left" => Key::LeftArrow,
+        "right" => Key::RightArrow,
+        "f1" => Key::F1,
+        "f2" => Key::F2,
+        "f3" => Key::F3,
+        "f4" => Key::F4,
+        "f5" => Key::F5,
+        "f6" => Key::F6,
+        "f7" => Key::F7,
+        "f8" => Key::F8,
+        "f9" => Key::F9,
+        "f10" => Key::F10,
+        "f11" => Key::F11,
+        "f12" => Key::F12,
+        "" => bail!("empty key name"),
+        _ => {
+            if let Some(hex) = lower.strip_prefix("0x") {
+                Key::Raw(
+                    u16::from_str_radix(hex, 16)
+                        .with_context(|| format!("invalid raw keycode `{}`", name))?,
+                )
+            } else {
+                let mut chars = lower.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Key::Layout(c),
+                    _ => bail!("unknown key `{}`", name),
+                }
+            }
+        }
+    };
+    Ok(key)
+}
+
+fn parse_mouse_button(name: &str) -> Result<MouseButton> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "left" => Ok(MouseButton::Left),
+        "right" => Ok(MouseButton::Right),
+        "middle" => Ok(MouseButton::Middle),
+        _ => bail!(
+            "unknown mouse button `{}` (expected `left`, `right` or `middle`)",
+            name
+        ),
+    }
+}
+
+/// `up` / `down` は両方、`gyro:up` のように対象を指定することもできる
+fn parse_sensitivity(arg: &str) -> Result<Action> {
+    let (target, direction) = match arg.split_once(':') {
+        Some((target, direction)) => (target.parse()?, direction),
+        None => (Target::Both, arg),
+    };
+    let step = match direction.trim().to_ascii_lowercase().as_str() {
+        "up" => 1,
+        "down" => -1,
+        _ => bail!(
+            "unknown sensitivity step `{}` (expected `up` or `down`)",
+            direction
+        ),
+    };
+    Ok(Action::Sensitivity { target, step })
+}
+
+fn parse_scroll(arg: &str) -> Result<Action> {
+    let (direction, amount) = match arg.split_once(':') {
+        Some((direction, amount)) => (
+            direction,
+            amount
+                .trim()
+                .parse::<i32>()
+                .with_context(|| format!("invalid scroll amount `{}`", amount))?,
+        ),
+        None => (arg, 1),
+    };
+    let action = match direction.trim().to_ascii_lowercase().as_str() {
+        "up" => Action::Scroll { x: 0, y: -amount },
+        "down" => Action::Scroll { x: 0, y: amount },
+        "left" => Action::Scroll { x: -amount, y: 0 },
+        "right" => Action::Scroll { x: amount, y: 0 },
+        _ => bail!(
+            "unknown scroll direction `{}` (expected `up`, `down`, `left` or `right`)",
+            direction
+        ),
+    };
+    Ok(action)
+}
+
+/// 同時押しの割り当てを読み込み、`base` の同じ組み合わせを置き換える
+///
+/// `none` を割り当てると取り消せる。ボタンの多いものを優先して判定する。
+fn parse_chords(base: Vec<Chord>, chords: HashMap<String, Action>) -> Result<Vec<Chord>> {
+    let mut merged = base;
+    for (buttons, action) in chords {
+        let chord = Chord::parse(&buttons, action)?;
+        merged.retain(|c| {
+            c.buttons.len() != chord.buttons.len()
+                || !c.buttons.iter().all(|b| chord.buttons.contains(b))
+        });
+        merged.push(chord);
+    }
+    merged.retain(|chord| chord.action != Action::None);
+    merged.sort_by_key(|chord| (std::cmp::Reverse(chord.buttons.len()), chord.to_string()));
+    Ok(merged)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFile {
+    name: Option<String>,
+    #[serde(default)]
+    controllers: Vec<String>,
+    #[serde(default)]
+    buttons: HashMap<JoyKey, Binding>,
+    #[serde(default)]
+    layers: HashMap<String, HashMap<JoyKey, Binding>>,
+    #[serde(default)]
+    chords: HashMap<String, Action>,
+    #[serde(default)]
+    gestures: GestureSettings,
+    #[serde(default)]
+    gyro: GyroSettings,
+    #[serde(default)]
+    stick: StickSettings,
+    #[serde(default)]
+    haptics: HapticSettings,
+    #[serde(default)]
+    precision: PrecisionSettings,
+    #[serde(default)]
+    pointer: PointerSettings,
+    #[serde(default)]
+    scroll: ScrollSettings,
+}
+
+/// ボタンと動作の対応表
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    /// このプロファイルを使うコントローラーのID(空なら割り当てのないコントローラー全て)
+    pub controllers: Vec<String>,
+    buttons: HashMap<JoyKey, Binding>,
+    layers: HashMap<String, HashMap<JoyKey, Binding>>,
+    pub chords: Vec<Chord>,
+    pub gestures: GestureSettings,
+    pub gyro: GyroSettings,
+    pub stick: StickSettings,
+    pub haptics: HapticSettings,
+    pub precision: PrecisionSettings,
+    pub pointer: PointerSettings,
+    pub scroll: ScrollSettings,
+}
+
+impl Profile {
+    /// ファイルから読み込む。記載のないボタンはデフォルトの割り当てを使う。
+    pub fn load(path: &Path) -> Result<Profile> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("error reading the profile {}", path.display()))?;
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Profile::parse(&name, &text).with_context(|| format!("invalid profile {}", path.display()))
+    }
+
+    /// `name` はファイル内で名前が指定されていない場合に使う
+    pub fn parse(name: &str, text: &str) -> Result<Profile> {
+        let file: ProfileFile = toml::from_str(text)?;
+        let mut buttons = Profile::default().buttons;
+        buttons.extend(file.buttons);
+        let profile = Profile {
+            name: file.name.unwrap_or_else(|| name.to_string()),
+            controllers: file.controllers,
+            buttons,
+            layers: file.layers,
+            chords: parse_chords(Profile::default().chords, file.chords)?,
+            gestures: file.gestures,
+            gyro: file.gyro,
+            stick: file.stick,
+            haptics: file.haptics,
+            precision: file.precision,
+            pointer: file.pointer,
+            scroll: file.scroll,
+        };
+        profile.validate()?;
+        Ok(profile)
+    }
+
+    /// コントローラー `id` に割り当てられているか
+    ///
+    /// Joy-Conの組(`左のMAC+右のMAC`)はどちらかのMACアドレスでも指定できる。
+    fn assigned_to(&self, id: &str) -> bool {
+        self.controllers.iter().any(|assigned| {
+            assigned.eq_ignore_ascii_case(id)
+                || id
+                    .split('+')
+                    .any(|part| assigned.eq_ignore_ascii_case(part))
+        })
+    }
+
+    /// 全ての割り当てを(レイヤー名, ボタン, 動作)で列挙する
+    fn bindings(&self) -> impl Iterator<Item = (Option<&String>, &JoyKey, &Action)> {
+        self.buttons
+            .iter()
+            .map(|(button, binding)| (None, button, binding))
+            .chain(self.layers.iter().flat_map(|(layer, buttons)| {
+                buttons
+                    .iter()
+                    .map(move |(button, binding)| (Some(layer), button, binding))
+            }))
+            .flat_map(|(layer, button, binding)| {
+                binding
+                    .actions()
+                    .into_iter()
+                    .map(move |action| (layer, button, action))
+            })
+    }
+
+    fn validate(&self) -> Result<()> {
+        self.gestures.validate()?;
+        for chord in &self.chords {
+            if let Action::Layer(name) = &chord.action {
+                if !self.layers.contains_key(name) {
+                    bail!("chord `{}` refers to an undefined layer `{}`", chord, name);
+                }
+            }
+        }
+        self.gyro.validate()?;
+        self.stick.validate()?;
+        self.haptics.validate()?;
+        self.precision.validate()?;
+        self.pointer.validate()?;
+        self.scroll.validate()?;
+        for (layer, button, action) in self.bindings() {
+            if let Action::Layer(name) = action {
+                if !self.layers.contains_key(name) {
+                    match layer {
+                        Some(layer) => bail!(
+                            "button `{}` in layer `{}` refers to an undefined layer `{}`",
+                            button,
+                            layer,
+                            name
+                        ),
+                        None => bail!(
+                            "button `{}` refers to an undefined layer `{}`",
+                            button,
+                            name
+                        ),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `gyro:on` が割り当てられている場合、ジャイロは普段は無効になる
+    pub fn gyro_on_held(&self) -> bool {
+        self.bindings()
+            .map(|(_, _, action)| action)
+            .chain(self.chords.iter().map(|chord| &chord.action))
+            .any(|action| action == &Action::GyroOn)
+    }
+
+    /// 有効なレイヤーを後ろから順に探し、なければ基本の割り当てを使う
+    pub fn binding(&self, button: JoyKey, layers: &[String]) -> &Binding {
+        const NONE: &Binding = &Binding::Press(Action::None);
+        layers
+            .iter()
+            .rev()
+            .find_map(|layer| self.layers.get(layer)?.get(&button))
+            .or_else(|| self.buttons.get(&button))
+            .unwrap_or(NONE)
+    }
+}
+
+impl Profile {
+    /// 組み込みのプロファイルにOSごとの割り当てを重ねる
+    fn with_platform(platform: &str) -> Profile {
+        let file: ProfileFile =
+            toml::from_str(DEFAULT_PROFILE).expect("the default profile is valid");
+        let platform: ProfileFile =
+            toml::from_str(platform).expect("the platform profile is valid");
+        let mut buttons = file.buttons;
+        buttons.extend(platform.buttons);
+        Profile {
+            name: file.name.unwrap_or_else(|| "default".to_string()),
+            controllers: Vec::new(),
+            buttons,
+            layers: file.layers,
+            chords: parse_chords(vec![], file.chords).expect("the default profile is valid"),
+            gestures: file.gestures,
+            gyro: file.gyro,
+            stick: file.stick,
+            haptics: file.haptics,
+            precision: file.precision,
+            pointer: file.pointer,
+            scroll: file.scroll,
+        }
+    }
+}
+
+/// コントローラー `id` で使うプロファイル
+///
+/// `controllers` にIDを書いたものがあればそれを、なければ `controllers` のないものを使う。
+/// どちらもなければ組み込みのプロファイルを使う。
+pub fn for_controller(profiles: &[Profile], id: &str) -> Vec<Profile> {
+    let assigned: Vec<_> = profiles
+        .iter()
+        .filter(|profile| profile.assigned_to(id))
+        .cloned()
+        .collect();
+    if !assigned.is_empty() {
+        return assigned;
+    }
+    let shared: Vec<_> = profiles
+        .iter()
+        .filter(|profile| profile.controllers.is_empty())
+        .cloned()
+        .collect();
+    if shared.is_empty() {
+        vec![Profile::default()]
+    } else {
+        shared
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::with_platform(PLATFORM_PROFILE)
+    }
+}
+
+#[test]
+fn default_profile() {
+    // SL, SR は Joy-Con を1本ずつ持った時にしか押せないので割り当てない
+    let buttons = || {
+        JoyKey::ALL
+            .iter()
+            .copied()
+            .filter(|b| ![JoyKey::SL, JoyKey::SR].contains(b))
+    };
+    let profile = Profile::default();
+    for button in buttons() {
+        assert_ne!(
+            profile.binding(button, &[]),
+            &Binding::Press(Action::None),
+            "{}",
+            button
+        );
+    }
+    assert_eq!(
+        profile.binding(JoyKey::ZR, &[]),
+        &Binding::Press(Action::Click(MouseButton::Left))
+    );
+
+    // タブを閉じるショートカットはOSごとに違う
+    let close = |platform, modifier| {
+        let profile = Profile::with_platform(platform);
+        profile.validate().unwrap();
+        for button in buttons() {
+            assert_ne!(profile.binding(button, &[]), &Binding::Press(Action::None));
+        }
+        assert_eq!(
+            profile.binding(JoyKey::Minus, &[]),
+            &Binding::Press(Action::Key(vec![modifier, Key::Layout('w')]))
+        );
+    };
+    close(include_str!("../profiles/macos.toml"), Key::Meta);
+    close(include_str!("../profiles/linux.toml"), Key::Control);
+    close(include_str!("../profiles/windows.toml"), Key::Control);
+
+    // 横持ちでは SL・SR(l・r)でクリックする
+    let profile = Profile::parse("sideways", include_str!("../profiles/sideways.toml")).unwrap();
+    assert_eq!(
+        profile.binding(JoyKey::L, &[]),
+        &Binding::Press(Action::Click(MouseButton::Left))
+    );
+}
+
+#[test]
+fn parse_actions() {
+    assert_eq!("none".parse::<Action>().unwrap(), Action::None);
+    assert_eq!(
+        "key:Ctrl+Shift+Tab".parse::<Action>().unwrap(),
+        Action::Key(vec![Key::Control, Key::Shift, Key::Tab])
+    );
+    assert_eq!(
+        "key:cmd+w".parse::<Action>().unwrap(),
+        Action::Key(vec![Key::Meta, Key::Layout('w')])
+    );
+    assert_eq!(
+        "scroll:up:3".parse::<Action>().unwrap(),
+        Action::Scroll { x: 0, y: -3 }
+    );
+    assert!("key:ctrl+nope".parse::<Action>().is_err());
+    assert!("mouse:side".parse::<Action>().is_err());
+    assert!("key".parse::<Action>().is_err());
+    assert_eq!(
+        "layer:fn".parse::<Action>().unwrap(),
+        Action::Layer("fn".to_string())
+    );
+    assert_eq!(
+        "profile:next".parse::<Action>().unwrap(),
+        Action::NextProfile
+    );
+    assert!("layer:".parse::<Action>().is_err());
+    assert_eq!(
+        "sens:gyro:down".parse::<Action>().unwrap(),
+        Action::Sensitivity {
+            target: Target::Gyro,
+            step: -1
+        }
+    );
+    assert_eq!(
+        "sens:up".parse::<Action>().unwrap(),
+        Action::Sensitivity {
+            target: Target::Both,
+            step: 1
+        }
+    );
+    assert!("sens:mouse:up".parse::<Action>().is_err());
+    assert_eq!("precision".parse::<Action>().unwrap(), Action::Precision);
+    assert!("precision:on".parse::<Action>().is_err());
+}
+
+#[test]
+fn layers() {
+    let profile = Profile::parse(
+        "test",
+        "[buttons]\ncapture = \"layer:fn\"\n[layers.fn]\ne = \"key:ctrl+w\"\n",
+    )
+    .unwrap();
+    let layers = ["fn".to_string()];
+    assert_eq!(
+        profile.binding(JoyKey::E, &layers),
+        &Binding::Press(Action::Key(vec![Key::Control, Key::Layout('w')]))
+    );
+    assert_eq!(
+        profile.binding(JoyKey::S, &layers),
+        profile.binding(JoyKey::S, &[])
+    );
+
+    let err = Profile::parse("test", "[buttons]\ncapture = \"layer:fn\"\n").unwrap_err();
+    assert!(err.to_string().contains("undefined layer `fn`"), "{}", err);
+}
+
+#[test]
+fn invalid_profile() {
+    let err = Profile::parse("test", "[buttons]\nfoo = \"none\"\n").unwrap_err();
+    assert!(err.to_string().contains("unknown button `foo`"), "{}", err);
+    let err = Profile::parse("test", "[buttons]\ne = \"key:meta+nope\"\n").unwrap_err();
+    assert!(err.to_string().contains("unknown key `nope`"), "{}", err);
+    let err = Profile::parse("test", "[gyro]\nspace = \"screen\"\n").unwrap_err();
+    assert!(err.to_string().contains("unknown gyro space"), "{}", err);
+}
+
+#[test]
+fn gyro_settings() {
+    use crate::gyro::GyroSpace;
+
+    assert_eq!(Profile::default().gyro.space, GyroSpace::Player);
+    let profile = Profile::parse("test", "[gyro]\nspace = \"world\"\n").unwrap();
+    assert_eq!(profile.gyro.space, GyroSpace::World);
+
+    use crate::pointer::PointerMode;
+
+    assert_eq!(Profile::default().pointer.mode, PointerMode::Relative);
+    let profile = Profile::parse(
+        "test",
+        "[buttons]\nhome = \"recenter\"\n[pointer]\nmode = \"absolute\"\nfov = 30.0\n",
+    )
+    .unwrap();
+    assert_eq!(profile.pointer.mode, PointerMode::Absolute);
+    assert_eq!(
+        profile.binding(JoyKey::Home, &[]),
+        &Binding::Press(Action::Recenter)
+    );
+    assert!(Profile::parse("test", "[pointer]\nfov = 180.0\n").is_err());
+}
+
+#[test]
+fn gestures_and_chords() {
+    let profile = Profile::parse(
+        "test",
+        "[buttons]\nr = { tap = \"click:right\", hold = \"mouse:right\" }\n\
+         [chords]\n\"zl+zr\" = \"gyro:off\"\n\"l+r+zl\" = \"profile:next\"\n\
+         [gestures]\nhold_time = 0.5\n",
+    )
+    .unwrap();
+    assert_eq!(
+        profile.binding(JoyKey::R, &[]),
+        &Binding::Gestures {
+            tap: Action::Click(MouseButton::Right),
+            hold: Action::Mouse(MouseButton::Right),
+            double: Action::None,
+        }
+    );
+    let chords: Vec<_> = profile.chords.iter().map(|c| c.to_string()).collect();
+    assert_eq!(chords, ["l+r+zl", "zl+zr"]);
+    assert_eq!(profile.gestures.hold_time, 0.5);
+
+    // デフォルトでは同時押しを割り当てない("none" は割り当てないのと同じ)
+    assert!(Profile::default().chords.is_empty());
+    let profile = Profile::parse("test", "[chords]\n\"up+home\" = \"none\"\n").unwrap();
+    assert!(profile.chords.is_empty());
+
+    let err = Profile::parse("test", "[buttons]\nr = {}\n").unwrap_err();
+    assert!(err.to_string().contains("at least one of"), "{}", err);
+    let err = Profile::parse("test", "[buttons]\nr = { tap = \"click:up\" }\n").unwrap_err();
+    assert!(err.to_string().contains("unknown mouse button"), "{}", err);
+    let err = Profile::parse("test", "[chords]\nr = \"none\"\n").unwrap_err();
+    assert!(err.to_string().contains("at least two buttons"), "{}", err);
+}
+
+#[test]
+fn macros_and_repeat() {
+    use std::time::Duration;
+
+    let profile = Profile::parse(
+        "test",
+        "[buttons]\ne = \"text:Hello, world\"\n\
+         s = [\"key:ctrl+c\", \"wait:100\", \"scroll:down\"]\n\
+         up = { action = \"key:up\", repeat = 0.05 }\n",
+    )
+    .unwrap();
+    assert_eq!(
+        profile.binding(JoyKey::E, &[]),
+        &Binding::Press(Action::Text("Hello, world".to_string()))
+    );
+    assert_eq!(
+        profile.binding(JoyKey::S, &[]),
+        &Binding::Press(Action::Macro(vec![
+            Step::Action(Action::Key(vec![Key::Control, Key::Layout('c')])),
+            Step::Wait(100),
+            Step::Action(Action::Scroll { x: 0, y: 1 }),
+        ]))
+    );
+    assert_eq!(
+        profile.binding(JoyKey::Up, &[]),
+        &Binding::Repeat {
+            action: Action::Key(vec![Key::UpArrow]),
+            delay: Duration::from_millis(50),
+            interval: Duration::from_millis(50),
+        }
+    );
+
+    let err = Profile::parse("test", "[buttons]\ne = [\"layer:fn\"]\n").unwrap_err();
+    assert!(
+        err.to_string().contains("cannot be used in a macro"),
+        "{}",
+        err
+    );
+    let err = Profile::parse("test", "[buttons]\ne = [\"wait:soon\"]\n").unwrap_err();
+    assert!(err.to_string().contains("invalid wait"), "{}", err);
+    let err = Profile::parse(
+        "test",
+        "[buttons]\ne = { action = \"key:a\", repeat = 0.0 }\n",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("must be positive"), "{}", err);
+}
+
+#[test]
+fn assign_to_controllers() {
+    let presenter = Profile::parse(
+        "presenter",
+        "controllers = [\"98:B6:E9:00:00:01\"]\n[buttons]\ne = \"key:right\"\n",
+    )
+    .unwrap();
+    let shared = Profile::parse("shared", "").unwrap();
+    let profiles = [presenter, shared];
+    let names = |id| {
+        for_controller(&profiles, id)
+            .into_iter()
+            .map(|profile| profile.name)
+            .collect::<Vec<_>>()
+    };
+
+    // 大文字・小文字は区別せず、Joy-Conの組はどちらかのMACアドレスで選ぶ
+    assert_eq!(names("98:b6:e9:00:00:01"), ["presenter"]);
+    assert_eq!(names("98:b6:e9:00:00:02+98:b6:e9:00:00:01"), ["presenter"]);
+    assert_eq!(names("98:b6:e9:00:00:03"), ["shared"]);
+    assert_eq!(
+        for_controller(&profiles[..1], "98:b6:e9:00:00:03")[0].name,
+        "default"
+    );
+}
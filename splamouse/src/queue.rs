@@ -0,0 +1,129 @@
+use crate::output::{Event, EventSink, Output};
+use anyhow::{anyhow, Result};
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+enum Command {
+    Event(Event),
+    /// ここまでのイベントを出力したら知らせる
+    Flush(Sender<()>),
+}
+
+/// 出力を専用のスレッドで順番に実行するキュー
+///
+/// 入力の処理はキーの間隔を待たずに進み、どのスレッドから送っても送った順に出力される。
+/// 複製したものは同じスレッドに送る。全て破棄するとスレッドも終了する。
+#[derive(Clone)]
+pub struct Queue {
+    sender: Sender<Command>,
+    resolution: f64,
+}
+
+impl Queue {
+    /// 出力スレッドを起動する。出力先はスレッドの中で開く。
+    pub fn spawn(open: impl FnOnce() -> Result<Box<dyn Output>> + Send + 'static) -> Result<Queue> {
+        let (sender, receiver) = channel();
+        let (opened, opened_receiver) = channel();
+        thread::spawn(move || {
+            let output = match open() {
+                Ok(output) => {
+                    let _ = opened.send(Ok(output.scroll_resolution()));
+                    output
+                }
+                Err(e) => {
+                    let _ = opened.send(Err(e));
+                    return;
+                }
+            };
+            if let Err(e) = run(output, receiver) {
+                eprintln!("error in the output thread: {:?}", e);
+            }
+        });
+        let resolution = opened_receiver
+            .recv()
+            .map_err(|_| anyhow!("the output thread stopped"))??;
+        Ok(Queue { sender, resolution })
+    }
+
+    /// 送ったイベントが全て出力されるまで待つ
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn flush(&self) -> Result<()> {
+        let (done, done_receiver) = channel();
+        self.send(Command::Flush(done))?;
+        done_receiver
+            .recv()
+            .map_err(|_| anyhow!("the output thread stopped"))
+    }
+
+    fn send(&self, command: Command) -> Result<()> {
+        self.sender
+            .send(command)
+            .map_err(|_| anyhow!("the output thread stopped"))
+    }
+}
+
+impl EventSink for Queue {
+    fn event(&mut self, event: Event) -> Result<()> {
+        self.send(Command::Event(event))
+    }
+
+    fn scroll_resolution(&self) -> f64 {
+        self.resolution
+    }
+}
+
+fn run(mut output: Box<dyn Output>, receiver: Receiver<Command>) -> Result<()> {
+    let gap = output.key_gap();
+    for command in receiver {
+        match command {
+            Command::Event(event) => {
+                let is_key = matches!(event, Event::KeyDown { .. } | Event::KeyUp { .. });
+                output.apply(event)?;
+                // 出力先によっては、キー入力の間隔を空けないと順番が前後してしまう
+                if is_key && !gap.is_zero() {
+                    thread::sleep(gap);
+                }
+            }
+            Command::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn ordered_from_threads() {
+    use crate::output::Recording;
+    use enigo::Key;
+
+    let recording = Recording::new();
+    let sink = recording.clone();
+    let queue = Queue::spawn(move || Ok(Box::new(sink))).unwrap();
+
+    let mut keys = queue.clone();
+    keys.key_down(Key::Meta).unwrap();
+    keys.key_down(Key::Tab).unwrap();
+    thread::spawn({
+        let mut queue = queue.clone();
+        move || queue.move_relative(3, -1).unwrap()
+    })
+    .join()
+    .unwrap();
+    keys.key_up(Key::Tab).unwrap();
+    keys.key_up(Key::Meta).unwrap();
+    queue.flush().unwrap();
+
+    assert_eq!(
+        recording.take(),
+        vec![
+            Event::KeyDown { key: Key::Meta },
+            Event::KeyDown { key: Key::Tab },
+            Event::Move { x: 3, y: -1 },
+            Event::KeyUp { key: Key::Tab },
+            Event::KeyUp { key: Key::Meta },
+        ]
+    );
+}
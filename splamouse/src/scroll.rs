@@ -0,0 +1,233 @@
+use anyhow::{bail, Result};
+use cgmath::{vec2, InnerSpace, Vector2, Zero};
+use serde::{de, Deserialize, Deserializer};
+use std::{fmt, str::FromStr};
+
+/// L-スティックによるスクロールの方法
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum ScrollMode {
+    /// 傾けた量に応じてスクロールし、離すと止まる
+    #[default]
+    Linear,
+    /// 弾くと離した後も惰性でスクロールし、摩擦で止まる
+    Kinetic,
+}
+
+impl ScrollMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ScrollMode::Linear => "linear",
+            ScrollMode::Kinetic => "kinetic",
+        }
+    }
+}
+
+impl fmt::Display for ScrollMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ScrollMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "linear" => Ok(ScrollMode::Linear),
+            "kinetic" => Ok(ScrollMode::Kinetic),
+            _ => bail!(
+                "unknown scroll mode `{}` (expected `linear` or `kinetic`)",
+                s
+            ),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ScrollMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// プロファイルの `[scroll]` の設定
+///
+/// 速さはホイールのノッチ数で表す(`--stick` と感度の段階も掛かる)。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScrollSettings {
+    pub mode: ScrollMode,
+    /// 端まで倒した時の速さ(notch/s)
+    pub speed: f64,
+    /// これより短く倒して離すと弾いたとみなす(s)
+    pub flick_time: f64,
+    /// 惰性の減衰率(1/s)。大きいほど早く止まる。
+    pub friction: f64,
+    /// これより遅くなったら止める(notch/s)
+    pub min_speed: f64,
+    /// この角速度(deg/s)より速くひねると惰性をつける。0で無効。
+    pub twist_threshold: f64,
+    /// ひねった1度あたりに加える速さ(notch/s)
+    pub twist_sens: f64,
+}
+
+impl ScrollSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.speed < 0.0 || self.flick_time < 0.0 || self.min_speed < 0.0 {
+            bail!("scroll speed, flick_time and min_speed must not be negative");
+        }
+        if self.friction <= 0.0 {
+            bail!("scroll friction must be positive (got {})", self.friction);
+        }
+        if self.twist_threshold < 0.0 {
+            bail!("scroll twist_threshold must not be negative");
+        }
+        Ok(())
+    }
+}
+
+impl Default for ScrollSettings {
+    fn default() -> Self {
+        ScrollSettings {
+            mode: ScrollMode::default(),
+            speed: 40.0,
+            flick_time: 0.25,
+            friction: 2.5,
+            min_speed: 0.5,
+            twist_threshold: 0.0,
+            twist_sens: 1.0,
+        }
+    }
+}
+
+/// 惰性のあるスクロール
+#[derive(Debug)]
+pub struct KineticScroll {
+    /// スクロールの速さ(notch/s、右・下が正)
+    velocity: Vector2<f64>,
+    /// 倒している時間(s)と、その間で最も速かった速さ
+    touch: Option<(f64, Vector2<f64>)>,
+}
+
+impl KineticScroll {
+    pub fn new() -> Self {
+        KineticScroll {
+            velocity: Vector2::zero(),
+            touch: None,
+        }
+    }
+
+    /// `dt` 秒間のスティックの傾き(上が正)とひねりの角速度(deg/s、反時計回りが正)から、
+    /// ホイールの移動量(notch)を求める
+    pub fn update(
+        &mut self,
+        settings: &ScrollSettings,
+        stick: Vector2<f64>,
+        twist: f64,
+        gain: f64,
+        dt: f64,
+    ) -> Vector2<f64> {
+        if stick.magnitude2() > 0.0 {
+            // 触っている間は傾きに従い、惰性は止める
+            let velocity = vec2(stick.x, -stick.y) * settings.speed * gain;
+            let (time, peak) = self.touch.get_or_insert((0.0, Vector2::zero()));
+            *time += dt;
+            if velocity.magnitude2() > peak.magnitude2() {
+                *peak = velocity;
+            }
+            self.velocity = velocity;
+        } else {
+            // 弾いた時は一番速かった速さで離れ、ゆっくり倒して離した時は止める
+            if let Some((time, peak)) = self.touch.take() {
+                self.velocity = if time <= settings.flick_time {
+                    peak
+                } else {
+                    Vector2::zero()
+                };
+            }
+            self.velocity *= (-settings.friction * dt).exp();
+        }
+
+        // つまみを回すように、ひねった角度に応じて惰性をつける(反時計回りで上へ)
+        if settings.twist_threshold > 0.0 && twist.abs() > settings.twist_threshold {
+            self.velocity.y -= twist * dt * settings.twist_sens * gain;
+        }
+
+        if self.touch.is_none() && self.velocity.magnitude() < settings.min_speed {
+            self.velocity = Vector2::zero();
+        }
+        self.velocity * dt
+    }
+}
+
+#[cfg(test)]
+fn run(
+    scroll: &mut KineticScroll,
+    settings: &ScrollSettings,
+    stick: Vector2<f64>,
+    twist: f64,
+    seconds: f64,
+) -> Vector2<f64> {
+    let dt = 0.005;
+    let mut total = Vector2::zero();
+    for _ in 0..(seconds / dt).round() as usize {
+        total += scroll.update(settings, stick, twist, 1.0, dt);
+    }
+    total
+}
+
+#[test]
+fn flick_and_friction() {
+    let settings = ScrollSettings {
+        mode: ScrollMode::Kinetic,
+        ..ScrollSettings::default()
+    };
+    let mut scroll = KineticScroll::new();
+
+    // 倒している間は傾きに比例する
+    let moved = run(&mut scroll, &settings, vec2(0.0, 1.0), 0.0, 0.1);
+    assert!((moved.y + 4.0).abs() < 1e-6, "{:?}", moved);
+
+    // 弾いて離すと続き、だんだん遅くなって止まる
+    let first = run(&mut scroll, &settings, Vector2::zero(), 0.0, 0.5);
+    let second = run(&mut scroll, &settings, Vector2::zero(), 0.0, 0.5);
+    assert!(
+        first.y < second.y && second.y < 0.0,
+        "{:?} {:?}",
+        first,
+        second
+    );
+    run(&mut scroll, &settings, Vector2::zero(), 0.0, 5.0);
+    assert_eq!(
+        run(&mut scroll, &settings, Vector2::zero(), 0.0, 0.1),
+        Vector2::zero()
+    );
+
+    // 触ると止まり、ゆっくり倒して離すと惰性はつかない
+    run(&mut scroll, &settings, vec2(0.0, -1.0), 0.0, 0.1);
+    run(&mut scroll, &settings, vec2(0.0, 0.5), 0.0, 0.5);
+    assert_eq!(
+        run(&mut scroll, &settings, Vector2::zero(), 0.0, 0.1),
+        Vector2::zero()
+    );
+}
+
+#[test]
+fn twist_to_scroll() {
+    let settings = ScrollSettings {
+        mode: ScrollMode::Kinetic,
+        twist_threshold: 100.0,
+        ..ScrollSettings::default()
+    };
+    let mut scroll = KineticScroll::new();
+
+    // ゆっくりひねっても動かない
+    assert_eq!(
+        run(&mut scroll, &settings, Vector2::zero(), 50.0, 0.1),
+        Vector2::zero()
+    );
+    // 時計回りに素早くひねると下へ惰性がつく
+    run(&mut scroll, &settings, Vector2::zero(), -300.0, 0.1);
+    assert!(run(&mut scroll, &settings, Vector2::zero(), 0.0, 0.1).y > 0.0);
+}
@@ -0,0 +1,198 @@
+use anyhow::{bail, Context, Result};
+use joycon::joycon_sys::light::{PlayerLight, PlayerLights};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// 感度を変える対象
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Target {
+    Gyro,
+    Stick,
+    /// ジャイロとスティックの両方
+    Both,
+}
+
+impl FromStr for Target {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gyro" => Ok(Target::Gyro),
+            "stick" => Ok(Target::Stick),
+            _ => bail!(
+                "unknown sensitivity target `{}` (expected `gyro` or `stick`)",
+                s
+            ),
+        }
+    }
+}
+
+/// コントローラーごとの感度の段階
+///
+/// プロファイルの感度に掛ける倍率を 1〜8 の段階で表す(4 が等倍)。
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Levels {
+    pub gyro: i32,
+    pub stick: i32,
+}
+
+impl Levels {
+    pub const MIN: i32 = 1;
+    pub const MAX: i32 = 8;
+    pub const DEFAULT: i32 = 4;
+    /// 1段階ごとの倍率
+    const RATIO: f64 = 1.25;
+
+    fn factor(level: i32) -> f64 {
+        Self::RATIO.powi(level - Self::DEFAULT)
+    }
+
+    pub fn gyro_factor(&self) -> f64 {
+        Self::factor(self.gyro)
+    }
+
+    pub fn stick_factor(&self) -> f64 {
+        Self::factor(self.stick)
+    }
+
+    /// `step` 段階変え、表示する段階(両方なら `gyro`)を返す
+    pub fn step(&mut self, target: Target, step: i32) -> i32 {
+        let change = |level: &mut i32| *level = (*level + step).clamp(Self::MIN, Self::MAX);
+        match target {
+            Target::Gyro => {
+                change(&mut self.gyro);
+                self.gyro
+            }
+            Target::Stick => {
+                change(&mut self.stick);
+                self.stick
+            }
+            Target::Both => {
+                change(&mut self.gyro);
+                change(&mut self.stick);
+                self.gyro
+            }
+        }
+    }
+
+    /// 段階をプレイヤーランプで表す(1〜4 は点灯の数、5〜8 は全て点灯し、超えた分を点滅)
+    pub fn lights(level: i32) -> PlayerLights {
+        let light = |i| Self::light(level, i);
+        PlayerLights::new(light(0), light(1), light(2), light(3))
+    }
+
+    fn light(level: i32, i: i32) -> PlayerLight {
+        if i < level - 4 {
+            PlayerLight::Blinking
+        } else if i < level {
+            PlayerLight::On
+        } else {
+            PlayerLight::Off
+        }
+    }
+}
+
+impl Default for Levels {
+    fn default() -> Self {
+        Levels {
+            gyro: Self::DEFAULT,
+            stick: Self::DEFAULT,
+        }
+    }
+}
+
+/// コントローラー(MACアドレス)ごとに感度の段階を保存するファイル
+#[derive(Debug)]
+pub struct LevelStore {
+    path: PathBuf,
+    controllers: HashMap<String, Levels>,
+}
+
+impl LevelStore {
+    /// 設定ディレクトリの `splamouse/sensitivity.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = if cfg!(windows) {
+            PathBuf::from(env::var_os("APPDATA")?)
+        } else {
+            env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))?
+        };
+        Some(dir.join("splamouse").join("sensitivity.toml"))
+    }
+
+    /// ファイルがなければ空で始める
+    pub fn load(path: &Path) -> Result<LevelStore> {
+        let controllers = match fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text)
+                .with_context(|| format!("invalid sensitivity file {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("error reading the sensitivity file {}", path.display())
+                })
+            }
+        };
+        Ok(LevelStore {
+            path: path.to_owned(),
+            controllers,
+        })
+    }
+
+    pub fn get(&self, controller: &str) -> Levels {
+        self.controllers
+            .get(controller)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// 変更してすぐにファイルに書き込む
+    pub fn set(&mut self, controller: &str, levels: Levels) -> Result<()> {
+        self.controllers.insert(controller.to_string(), levels);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, toml::to_string(&self.controllers)?)
+            .with_context(|| format!("error writing the sensitivity file {}", self.path.display()))
+    }
+}
+
+#[test]
+fn step_levels() {
+    let mut levels = Levels::default();
+    assert_eq!(levels.gyro_factor(), 1.0);
+    assert_eq!(levels.step(Target::Gyro, 1), 5);
+    assert_eq!(levels.gyro_factor(), 1.25);
+    assert_eq!(levels.stick, 4);
+    for _ in 0..10 {
+        levels.step(Target::Both, -1);
+    }
+    assert_eq!(levels, Levels { gyro: 1, stick: 1 });
+
+    let lights = |level| [0, 1, 2, 3].map(|i| Levels::light(level, i));
+    use PlayerLight::*;
+    assert_eq!(lights(1), [On, Off, Off, Off]);
+    assert_eq!(lights(4), [On, On, On, On]);
+    assert_eq!(lights(6), [Blinking, Blinking, On, On]);
+}
+
+#[test]
+fn store_per_controller() {
+    let path = env::temp_dir()
+        .join(format!("splamouse-test-{}", std::process::id()))
+        .join("sensitivity.toml");
+    let mut store = LevelStore::load(&path).unwrap();
+    assert_eq!(store.get("aa:bb"), Levels::default());
+    store.set("aa:bb", Levels { gyro: 6, stick: 3 }).unwrap();
+
+    let store = LevelStore::load(&path).unwrap();
+    assert_eq!(store.get("aa:bb"), Levels { gyro: 6, stick: 3 });
+    assert_eq!(store.get("cc:dd"), Levels::default());
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,345 @@
+use crate::output::{Event, EventSink, Output};
+use anyhow::{bail, Result};
+use cgmath::{vec2, InnerSpace, Vector2, Zero};
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// 複数のコントローラーの出力のまとめ方
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum Policy {
+    /// 全てのコントローラーの操作をそのまま重ねる
+    #[default]
+    Merge,
+    /// 最後に操作を始めたコントローラーだけを通す
+    LastActive,
+}
+
+impl Policy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::Merge => "merge",
+            Policy::LastActive => "last-active",
+        }
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Policy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "merge" => Ok(Policy::Merge),
+            "last-active" => Ok(Policy::LastActive),
+            _ => bail!("unknown policy `{}` (expected `merge` or `last-active`)", s),
+        }
+    }
+}
+
+/// これより長く操作しなかったコントローラーは、次の操作で出力を奪う
+const IDLE: Duration = Duration::from_millis(500);
+/// `MOTION_WINDOW` の間にこれだけ動かすと、意図した操作とみなす(px)
+const MOTION_PX: f64 = 32.0;
+/// 動きを合計する時間(ゆっくりした動きは手ぶれやドリフトとみなす)
+const MOTION_WINDOW: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Default)]
+struct State {
+    /// 出力を使っているコントローラー
+    active: Option<String>,
+    /// コントローラーごとの最後の操作の時刻
+    last: HashMap<String, Instant>,
+}
+
+impl State {
+    /// `id` が時刻 `now` に操作した。出力を使えるなら `true`。
+    ///
+    /// 操作を続けている間は、他のコントローラーに奪われたままになる。
+    fn claim(&mut self, id: &str, now: Instant) -> bool {
+        let resumed = self
+            .last
+            .get(id)
+            .is_none_or(|last| now.saturating_duration_since(*last) >= IDLE);
+        self.last.insert(id.to_string(), now);
+        if resumed || self.active.is_none() {
+            self.active = Some(id.to_string());
+        }
+        self.active.as_deref() == Some(id)
+    }
+
+    /// `id` が時刻 `now` に意図しない程度に動いた。出力を使っている時だけ通す。
+    fn keep(&mut self, id: &str, now: Instant) -> bool {
+        let active = self.active.as_deref() == Some(id);
+        if active {
+            self.last.insert(id.to_string(), now);
+        }
+        active
+    }
+
+    fn release(&mut self, id: &str) {
+        self.last.remove(id);
+        if self.active.as_deref() == Some(id) {
+            self.active = None;
+        }
+    }
+}
+
+/// コントローラー間で共有する出力の調停役
+///
+/// 複製したものは同じ状態を共有する。
+#[derive(Debug, Clone)]
+pub struct Arbiter {
+    policy: Policy,
+    state: Arc<Mutex<State>>,
+}
+
+impl Arbiter {
+    pub fn new(policy: Policy) -> Self {
+        Arbiter {
+            policy,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    /// コントローラー `id` の出力を、方針に従って `output` へ通す
+    pub fn output(&self, id: &str, output: Box<dyn Output>) -> Arbitrated {
+        Arbitrated {
+            arbiter: self.clone(),
+            id: id.to_string(),
+            output,
+            held: Vec::new(),
+            movement: Movement::new(),
+            position: None,
+        }
+    }
+
+    /// 切断したコントローラーの出力を他に譲る
+    pub fn release(&self, id: &str) {
+        self.state.lock().unwrap().release(id);
+    }
+
+    fn claim(&self, id: &str, now: Instant) -> bool {
+        match self.policy {
+            Policy::Merge => true,
+            Policy::LastActive => self.state.lock().unwrap().claim(id, now),
+        }
+    }
+
+    fn keep(&self, id: &str, now: Instant) -> bool {
+        match self.policy {
+            Policy::Merge => true,
+            Policy::LastActive => self.state.lock().unwrap().keep(id, now),
+        }
+    }
+}
+
+/// 手ぶれやドリフトと意図した動きを見分けるための、最近の動きの合計
+#[derive(Debug)]
+struct Movement {
+    /// 合計を始めた時刻
+    since: Option<Instant>,
+    /// `MOTION_PX` とスクロールの1ノッチを1とした動きの合計
+    sum: Vector2<f64>,
+}
+
+impl Movement {
+    fn new() -> Self {
+        Movement {
+            since: None,
+            sum: Vector2::zero(),
+        }
+    }
+
+    /// 時刻 `now` に `delta` 動いた。意図した動きなら `true`。
+    ///
+    /// 向きも合わせて合計するので、小刻みに揺れても打ち消し合う。
+    fn push(&mut self, delta: Vector2<f64>, now: Instant) -> bool {
+        match self.since {
+            Some(since) if now.saturating_duration_since(since) < MOTION_WINDOW => {}
+            _ => {
+                self.since = Some(now);
+                self.sum = Vector2::zero();
+            }
+        }
+        self.sum += delta;
+        self.sum.magnitude() >= 1.0
+    }
+}
+
+/// 一つのコントローラーの出力
+///
+/// ボタンの操作と、大きく動かした時だけを操作とみなす。
+pub struct Arbitrated {
+    arbiter: Arbiter,
+    id: String,
+    output: Box<dyn Output>,
+    /// 通したキー・マウスボタンの押下
+    held: Vec<Event>,
+    movement: Movement,
+    /// 絶対座標で最後に送った位置
+    position: Option<(i32, i32)>,
+}
+
+impl Arbitrated {
+    /// 押下を通していれば忘れて `true`
+    fn release(&mut self, down: Event) -> bool {
+        match self.held.iter().position(|held| *held == down) {
+            Some(i) => {
+                self.held.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// `delta` 動いた。通すなら `true`。
+    fn moved(&mut self, delta: Vector2<f64>, now: Instant) -> bool {
+        if self.movement.push(delta, now) {
+            self.arbiter.claim(&self.id, now)
+        } else {
+            self.arbiter.keep(&self.id, now)
+        }
+    }
+}
+
+impl EventSink for Arbitrated {
+    fn event(&mut self, event: Event) -> Result<()> {
+        // 押したものは出力を奪われても離せるようにし、通さなかったものは離す時も通さない
+        let now = Instant::now();
+        let pass = match event {
+            Event::KeyUp { key } => self.release(Event::KeyDown { key }),
+            Event::MouseUp { button } => self.release(Event::MouseDown { button }),
+            Event::Move { x, y } => self.moved(vec2(x as f64, y as f64) / MOTION_PX, now),
+            Event::MoveTo { x, y } => {
+                let delta = match self.position.replace((x, y)) {
+                    Some((last_x, last_y)) => vec2((x - last_x) as f64, (y - last_y) as f64),
+                    None => Vector2::zero(),
+                };
+                self.moved(delta / MOTION_PX, now)
+            }
+            Event::Scroll { x, y } => self.moved(vec2(x, y), now),
+            _ => self.arbiter.claim(&self.id, now),
+        };
+        if !pass {
+            return Ok(());
+        }
+        if let Event::KeyDown { .. } | Event::MouseDown { .. } = event {
+            self.held.push(event.clone());
+        }
+        self.output.apply(event)
+    }
+
+    fn scroll_resolution(&self) -> f64 {
+        self.output.scroll_resolution()
+    }
+}
+
+#[test]
+fn resume_after_idle() {
+    let mut state = State::default();
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+
+    // 後から操作を始めた方が奪い、先の方は操作を続ける間は通らない
+    assert!(state.claim("a", at(0)));
+    assert!(state.claim("b", at(10)));
+    assert!(!state.claim("a", at(20)));
+    for ms in [300, 600, 900].iter().copied() {
+        assert!(state.claim("b", at(ms)));
+    }
+    // 手を止めてから操作すると取り返す
+    assert!(state.claim("a", at(1000)));
+    assert!(!state.claim("b", at(1010)));
+
+    // 切断すると、操作を続けている方に移る
+    state.release("a");
+    assert!(state.claim("b", at(1020)));
+}
+
+#[test]
+fn last_active_wins() {
+    use crate::output::Recording;
+    use enigo::{Key, MouseButton};
+
+    let recording = Recording::new();
+    let arbiter = Arbiter::new(Policy::LastActive);
+    let mut a = arbiter.output("a", Box::new(recording.clone()));
+    let mut b = arbiter.output("b", Box::new(recording.clone()));
+
+    a.move_relative(0, 0).unwrap();
+    a.move_relative(40, 0).unwrap();
+    a.key_down(Key::Shift).unwrap();
+    b.move_relative(0, 40).unwrap();
+    a.move_relative(40, 0).unwrap();
+    a.mouse_down(MouseButton::Left).unwrap();
+    a.mouse_up(MouseButton::Left).unwrap();
+    a.key_up(Key::Shift).unwrap();
+    arbiter.release("b");
+    a.move_relative(2, 0).unwrap();
+    assert_eq!(
+        recording.take(),
+        vec![
+            Event::Move { x: 40, y: 0 },
+            Event::KeyDown { key: Key::Shift },
+            Event::Move { x: 0, y: 40 },
+            Event::KeyUp { key: Key::Shift },
+            Event::Move { x: 2, y: 0 },
+        ]
+    );
+
+    // 重ねる場合は全て通す
+    let arbiter = Arbiter::new(Policy::Merge);
+    let mut a = arbiter.output("a", Box::new(recording.clone()));
+    let mut b = arbiter.output("b", Box::new(recording.clone()));
+    a.move_relative(1, 0).unwrap();
+    b.move_relative(0, 1).unwrap();
+    a.move_relative(1, 0).unwrap();
+    assert_eq!(recording.take().len(), 3);
+}
+
+#[test]
+fn jitter_does_not_take_over() {
+    use crate::output::Recording;
+    use enigo::Key;
+
+    let recording = Recording::new();
+    let arbiter = Arbiter::new(Policy::LastActive);
+    let mut a = arbiter.output("a", Box::new(recording.clone()));
+    let mut b = arbiter.output("b", Box::new(recording.clone()));
+
+    // 置いてある方が1pxずつ揺れても、使っている方から奪わない
+    a.key_down(Key::Shift).unwrap();
+    for i in 0..100 {
+        let d = if i % 2 == 0 { 1 } else { -1 };
+        b.move_relative(d, 0).unwrap();
+        b.move_absolute(500 + d, 300).unwrap();
+        b.scroll(0.0, 0.01 * d as f64).unwrap();
+    }
+    a.move_relative(1, 0).unwrap();
+    assert_eq!(
+        recording.take(),
+        vec![
+            Event::KeyDown { key: Key::Shift },
+            Event::Move { x: 1, y: 0 }
+        ]
+    );
+
+    // ゆっくりしたドリフトも意図した動きとはみなさない
+    let mut movement = Movement::new();
+    let start = Instant::now();
+    for ms in (0..2000).step_by(15) {
+        let delta = vec2(1.0, 0.0) / MOTION_PX;
+        assert!(!movement.push(delta, start + Duration::from_millis(ms)));
+    }
+    assert!(movement.push(vec2(0.0, 1.0), start + Duration::from_millis(2000)));
+}
@@ -0,0 +1,203 @@
+use anyhow::{anyhow, bail, Result};
+use cgmath::{InnerSpace, Vector2};
+use hid_gamepad_types::StickResponse;
+use serde::{de, Deserialize, Deserializer};
+use std::{f64::consts::PI, fmt, str::FromStr};
+
+/// R-スティックの使い方
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub enum RightStickMode {
+    /// 傾けた量に応じてカーソルを動かす
+    #[default]
+    Mouse,
+    /// 傾けた方向へ一瞬で向き、スティックを回すとその分だけ回る
+    Flick,
+}
+
+impl RightStickMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            RightStickMode::Mouse => "mouse",
+            RightStickMode::Flick => "flick",
+        }
+    }
+}
+
+impl fmt::Display for RightStickMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RightStickMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "mouse" => Ok(RightStickMode::Mouse),
+            "flick" => Ok(RightStickMode::Flick),
+            _ => bail!(
+                "unknown right stick mode `{}` (expected `mouse` or `flick`)",
+                s
+            ),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for RightStickMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// プロファイルの `[stick]` の設定
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StickSettings {
+    /// L-スティック(ホイール)のデッドゾーンとカーブ
+    pub left_response: StickResponse,
+    /// R-スティックのデッドゾーンとカーブ(フリックスティックにも使う)
+    pub right_response: StickResponse,
+    pub right: RightStickMode,
+    /// フリックを始めるスティックの傾き(0〜1)
+    pub flick_threshold: f64,
+    /// フリックで向きを変えるのにかける時間(s)
+    pub flick_time: f64,
+    /// フリックの感度(px/deg)。ゲームでは360°回るのに必要な移動量に合わせる。
+    pub flick_sens: f64,
+}
+
+impl StickSettings {
+    pub fn validate(&self) -> Result<()> {
+        self.left_response
+            .validate()
+            .map_err(|e| anyhow!("invalid left_response: {}", e))?;
+        self.right_response
+            .validate()
+            .map_err(|e| anyhow!("invalid right_response: {}", e))?;
+        if !(self.flick_threshold > 0.0 && self.flick_threshold <= 1.0) {
+            bail!(
+                "flick_threshold must be between 0 and 1 (got {})",
+                self.flick_threshold
+            );
+        }
+        if self.flick_time < 0.0 || self.flick_sens < 0.0 {
+            bail!("flick_time and flick_sens must not be negative");
+        }
+        Ok(())
+    }
+}
+
+impl Default for StickSettings {
+    fn default() -> Self {
+        StickSettings {
+            left_response: StickResponse::default(),
+            right_response: StickResponse::default(),
+            right: RightStickMode::default(),
+            flick_threshold: 0.9,
+            flick_time: 0.1,
+            flick_sens: 50.0,
+        }
+    }
+}
+
+/// フリックスティック
+///
+/// スティックを倒すとその方向(上が正面)へ `flick_time` かけて向きを変え、
+/// 倒したまま回すと回した角度だけそのまま向きを変える。
+/// <http://gyrowiki.jibbsmart.com/blog:good-gyro-controls-part-2:the-flick-stick>
+#[derive(Debug, Default)]
+pub struct FlickStick {
+    /// 倒している間の直前の角度(rad)
+    last_angle: Option<f64>,
+    /// フリックの角度(rad)と経過時間(s)
+    flick: f64,
+    elapsed: f64,
+}
+
+impl FlickStick {
+    /// 一度離したとみなす傾き(閾値に対する割合)。境界でフリックが連続しないようにする。
+    const RELEASE_RATIO: f64 = 0.8;
+
+    pub fn new() -> Self {
+        FlickStick::default()
+    }
+
+    /// `dt` 秒間のスティックの状態から、横方向の移動量(px)を求める
+    pub fn update(&mut self, settings: &StickSettings, stick: Vector2<f64>, dt: f64) -> f64 {
+        let magnitude = stick.magnitude();
+        // 上を0として時計回り
+        let angle = stick.x.atan2(stick.y);
+        let mut turn = 0.0;
+
+        match self.last_angle {
+            None if magnitude >= settings.flick_threshold => {
+                // 前のフリックが途中なら残りを出し切る
+                turn += self.flick * (1.0 - self.progress(settings));
+                self.flick = angle;
+                self.elapsed = 0.0;
+                self.last_angle = Some(angle);
+            }
+            Some(_) if magnitude < settings.flick_threshold * Self::RELEASE_RATIO => {
+                self.last_angle = None;
+            }
+            Some(last) => {
+                // -π〜πに正規化した差分
+                let delta = angle - last;
+                turn += delta - (2.0 * PI) * ((delta + PI) / (2.0 * PI)).floor();
+                self.last_angle = Some(angle);
+            }
+            None => {}
+        }
+
+        let before = self.progress(settings);
+        self.elapsed += dt;
+        turn += self.flick * (self.progress(settings) - before);
+
+        turn.to_degrees() * settings.flick_sens
+    }
+
+    /// フリックの進み具合(0〜1)
+    fn progress(&self, settings: &StickSettings) -> f64 {
+        if settings.flick_time > 0.0 {
+            (self.elapsed / settings.flick_time).min(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+fn assert_near(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() < 1e-9, "{}", actual);
+}
+
+#[test]
+fn flick_and_rotate() {
+    use cgmath::vec2;
+
+    let settings = StickSettings {
+        right: RightStickMode::Flick,
+        flick_time: 0.1,
+        flick_sens: 1.0,
+        ..StickSettings::default()
+    };
+    let mut flick = FlickStick::new();
+    assert_near(flick.update(&settings, vec2(0.5, 0.0), 0.05), 0.0);
+
+    // 右に倒すと 0.1 秒かけて 90° 回る
+    assert_near(flick.update(&settings, vec2(1.0, 0.0), 0.05), 45.0);
+    assert_near(flick.update(&settings, vec2(1.0, 0.0), 0.05), 45.0);
+    assert_near(flick.update(&settings, vec2(1.0, 0.0), 0.05), 0.0);
+
+    // 下を通って左まで回すと、-180°をまたいでも回した分だけ回る
+    assert_near(flick.update(&settings, vec2(0.0, -1.0), 0.05), 90.0);
+    assert_near(flick.update(&settings, vec2(-1.0, 0.0), 0.05), 90.0);
+
+    // 離してから左上に倒す
+    assert_near(flick.update(&settings, vec2(0.0, 0.0), 0.05), 0.0);
+    let up_left = vec2(-1.0, 1.0).normalize();
+    assert_near(flick.update(&settings, up_left, 0.1), -45.0);
+}
@@ -0,0 +1,66 @@
+use crate::{
+    output::{self, Output},
+    profile::Step,
+};
+use anyhow::{anyhow, Result};
+use std::{
+    sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// マクロの待ち時間を数えるタイマー(コントローラーごとに一つ)
+///
+/// 待っている間も他の出力は止めず、時間になったら残りの手順を出力に送る。
+/// 複製したものは同じスレッドに送る。全て破棄するとスレッドも終了する。
+#[derive(Clone)]
+pub struct Timer {
+    sender: Sender<(Instant, Vec<Step>)>,
+}
+
+impl Timer {
+    /// タイマーのスレッドを起動する。出力はスレッドの中で作る。
+    pub fn spawn(open: impl FnOnce() -> Box<dyn Output> + Send + 'static) -> Timer {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            if let Err(e) = run(open(), receiver) {
+                eprintln!("error in the macro timer: {:?}", e);
+            }
+        });
+        Timer { sender }
+    }
+
+    /// `delay` 後に `steps` を実行する
+    pub fn schedule(&self, delay: Duration, steps: Vec<Step>) -> Result<()> {
+        self.sender
+            .send((Instant::now() + delay, steps))
+            .map_err(|_| anyhow!("the macro timer stopped"))
+    }
+}
+
+fn run(mut output: Box<dyn Output>, receiver: Receiver<(Instant, Vec<Step>)>) -> Result<()> {
+    // 予約した手順(時刻, 手順)
+    let mut pending: Vec<(Instant, Vec<Step>)> = vec![];
+    loop {
+        // 時間になった手順を早い順に実行し、途中に待ち時間があれば残りを予約し直す
+        while let Some(i) = (0..pending.len())
+            .filter(|i| pending[*i].0 <= Instant::now())
+            .min_by_key(|i| pending[*i].0)
+        {
+            let (at, steps) = pending.remove(i);
+            if let Some((delay, rest)) = output::perform_steps(&mut *output, &steps)? {
+                pending.push((at + delay, rest));
+            }
+        }
+
+        let received = match pending.iter().map(|(at, _)| *at).min() {
+            Some(next) => receiver.recv_timeout(next.saturating_duration_since(Instant::now())),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(scheduled) => pending.push(scheduled),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
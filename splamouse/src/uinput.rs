@@ -0,0 +1,344 @@
+use crate::{output::Output, pointer::Desktop};
+use anyhow::{Context, Result};
+use cgmath::Vector2;
+use enigo::{Key, MouseButton};
+use evdev::{
+    uinput::{VirtualDevice, VirtualDeviceBuilder},
+    AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key as Code, RelativeAxisType,
+    UinputAbsSetup,
+};
+
+/// Linuxの仮想入力デバイス(uinput)への出力
+///
+/// `/dev/uinput` への書き込み権限が必要。
+pub struct Uinput {
+    device: VirtualDevice,
+    /// 従来のホイールイベントを送るために貯めた高解像度の移動量(x, y)
+    wheel: [i32; 2],
+    /// 絶対座標で動かす時に使う、デスクトップ全体を範囲とするポインター(最初に使う時に作る)
+    pointer: Option<VirtualDevice>,
+    /// デスクトップ全体の範囲(左上, 右下)
+    bounds: (Vector2<i32>, Vector2<i32>),
+}
+
+impl Uinput {
+    /// 高解像度ホイールの1ノッチ
+    const WHEEL_UNIT: i32 = 120;
+
+    pub fn new(desktop: &Desktop) -> Result<Uinput> {
+        // 生のキーコードも送れるように、キーボードのキーは全て登録する
+        let mut keys: AttributeSet<Code> = (1..0x100).map(Code::new).collect();
+        keys.insert(Code::BTN_LEFT);
+        keys.insert(Code::BTN_RIGHT);
+        keys.insert(Code::BTN_MIDDLE);
+        let axes: AttributeSet<RelativeAxisType> = [
+            RelativeAxisType::REL_X,
+            RelativeAxisType::REL_Y,
+            RelativeAxisType::REL_WHEEL,
+            RelativeAxisType::REL_HWHEEL,
+            RelativeAxisType::REL_WHEEL_HI_RES,
+            RelativeAxisType::REL_HWHEEL_HI_RES,
+        ]
+        .iter()
+        .collect();
+        let device = VirtualDeviceBuilder::new()
+            .context("error opening /dev/uinput")?
+            .name("splamouse")
+            .with_keys(&keys)?
+            .with_relative_axes(&axes)?
+            .build()
+            .context("error creating the uinput device")?;
+        Ok(Uinput {
+            device,
+            wheel: [0, 0],
+            pointer: None,
+            bounds: desktop.bounds(),
+        })
+    }
+
+    /// 絶対座標のポインター(タブレットと同じく、範囲はデスクトップ全体に対応する)
+    fn pointer(&mut self) -> Result<&mut VirtualDevice> {
+        if self.pointer.is_none() {
+            let (min, max) = self.bounds;
+            let axis =
+                |axis, min, max| UinputAbsSetup::new(axis, AbsInfo::new(min, min, max, 0, 0, 0));
+            let mut keys = AttributeSet::<Code>::new();
+            // ボタンがないとポインターとして扱われない
+            keys.insert(Code::BTN_LEFT);
+            let pointer = VirtualDeviceBuilder::new()
+                .context("error opening /dev/uinput")?
+                .name("splamouse pointer")
+                .with_keys(&keys)?
+                .with_absolute_axis(&axis(AbsoluteAxisType::ABS_X, min.x, max.x))?
+                .with_absolute_axis(&axis(AbsoluteAxisType::ABS_Y, min.y, max.y))?
+                .build()
+                .context("error creating the uinput pointer")?;
+            self.pointer = Some(pointer);
+        }
+        Ok(self.pointer.as_mut().unwrap())
+    }
+
+    fn emit_key(&mut self, code: Code, value: i32) -> Result<()> {
+        self.device
+            .emit(&[InputEvent::new(EventType::KEY, code.code(), value)])?;
+        Ok(())
+    }
+
+    fn key(&mut self, key: Key, value: i32) -> Result<()> {
+        match key_code(key) {
+            Some(code) => self.emit_key(code, value),
+            None => {
+                eprintln!("{:?} is not supported by the uinput output", key);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Output for Uinput {
+    fn key_down(&mut self, key: Key) -> Result<()> {
+        self.key(key, 1)
+    }
+
+    fn key_up(&mut self, key: Key) -> Result<()> {
+        self.key(key, 0)
+    }
+
+    fn mouse_down(&mut self, button: MouseButton) -> Result<()> {
+        self.emit_key(button_code(button), 1)
+    }
+
+    fn mouse_up(&mut self, button: MouseButton) -> Result<()> {
+        self.emit_key(button_code(button), 0)
+    }
+
+    fn scroll(&mut self, x: f64, y: f64) -> Result<()> {
+        // REL_WHEEL は上が正
+        let hi_res = [
+            (x * Self::WHEEL_UNIT as f64).round() as i32,
+            (-y * Self::WHEEL_UNIT as f64).round() as i32,
+        ];
+        let axes = [
+            (
+                RelativeAxisType::REL_HWHEEL_HI_RES,
+                RelativeAxisType::REL_HWHEEL,
+            ),
+            (
+                RelativeAxisType::REL_WHEEL_HI_RES,
+                RelativeAxisType::REL_WHEEL,
+            ),
+        ];
+        let mut events = vec![];
+        for i in 0..2 {
+            if hi_res[i] == 0 {
+                continue;
+            }
+            let (hi_res_axis, axis) = axes[i];
+            events.push(InputEvent::new(
+                EventType::RELATIVE,
+                hi_res_axis.0,
+                hi_res[i],
+            ));
+            // 高解像度に対応していないアプリのために、1ノッチ貯まるごとに従来のイベントも送る
+            self.wheel[i] += hi_res[i];
+            let notches = self.wheel[i] / Self::WHEEL_UNIT;
+            if notches != 0 {
+                self.wheel[i] -= notches * Self::WHEEL_UNIT;
+                events.push(InputEvent::new(EventType::RELATIVE, axis.0, notches));
+            }
+        }
+        if !events.is_empty() {
+            self.device.emit(&events)?;
+        }
+        Ok(())
+    }
+
+    fn scroll_resolution(&self) -> f64 {
+        Self::WHEEL_UNIT as f64
+    }
+
+    fn text(&mut self, text: &str) -> Result<()> {
+        // 打てない文字があれば、途中まで入力せずにエラーにする
+        let codes = text
+            .chars()
+            .map(|c| {
+                layout_code(c)
+                    .with_context(|| format!("{:?} cannot be typed with the uinput output", c))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for (code, shift) in codes {
+            // US配列の大文字・記号はシフトを押して送る
+            if shift {
+                self.emit_key(Code::KEY_LEFTSHIFT, 1)?;
+            }
+            self.emit_key(code, 1)?;
+            self.emit_key(code, 0)?;
+            if shift {
+                self.emit_key(Code::KEY_LEFTSHIFT, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn move_relative(&mut self, x: i32, y: i32) -> Result<()> {
+        if x != 0 || y != 0 {
+            self.device.emit(&[
+                InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_X.0, x),
+                InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_Y.0, y),
+            ])?;
+        }
+        Ok(())
+    }
+
+    fn move_absolute(&mut self, x: i32, y: i32) -> Result<()> {
+        self.pointer()?.emit(&[
+            InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, x),
+            InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_Y.0, y),
+        ])?;
+        Ok(())
+    }
+}
+
+fn button_code(button: MouseButton) -> Code {
+    match button {
+        MouseButton::Left => Code::BTN_LEFT,
+        MouseButton::Right => Code::BTN_RIGHT,
+        _ => Code::BTN_MIDDLE,
+    }
+}
+
+/// enigoのキーをLinuxのキーコードに変換する
+///
+/// 一文字のキーはUS配列の位置で送る。生のキーコードはLinuxのキーコードとして扱う。
+#[allow(deprecated)]
+fn key_code(key: Key) -> Option<Code> {
+    let code = match key {
+        Key::Alt | Key::Option => Code::KEY_LEFTALT,
+        Key::Backspace => Code::KEY_BACKSPACE,
+        Key::CapsLock => Code::KEY_CAPSLOCK,
+        Key::Control => Code::KEY_LEFTCTRL,
+        Key::Delete => Code::KEY_DELETE,
+        Key::DownArrow => Code::KEY_DOWN,
+        Key::End => Code::KEY_END,
+        Key::Escape => Code::KEY_ESC,
+        Key::F1 => Code::KEY_F1,
+        Key::F2 => Code::KEY_F2,
+        Key::F3 => Code::KEY_F3,
+        Key::F4 => Code::KEY_F4,
+        Key::F5 => Code::KEY_F5,
+        Key::F6 => Code::KEY_F6,
+        Key::F7 => Code::KEY_F7,
+        Key::F8 => Code::KEY_F8,
+        Key::F9 => Code::KEY_F9,
+        Key::F10 => Code::KEY_F10,
+        Key::F11 => Code::KEY_F11,
+        Key::F12 => Code::KEY_F12,
+        Key::Home => Code::KEY_HOME,
+        Key::LeftArrow => Code::KEY_LEFT,
+        Key::Meta | Key::Command | Key::Super | Key::Windows => Code::KEY_LEFTMETA,
+        Key::PageDown => Code::KEY_PAGEDOWN,
+        Key::PageUp => Code::KEY_PAGEUP,
+        Key::Return => Code::KEY_ENTER,
+        Key::RightArrow => Code::KEY_RIGHT,
+        Key::Shift => Code::KEY_LEFTSHIFT,
+        Key::Space => Code::KEY_SPACE,
+        Key::Tab => Code::KEY_TAB,
+        Key::UpArrow => Code::KEY_UP,
+        Key::Raw(code) => Code::new(code),
+        Key::Layout(c) => return layout_code(c).map(|(code, _)| code),
+    };
+    Some(code)
+}
+
+/// US配列でその文字を打つキーと、シフトを押す必要があるか
+fn layout_code(c: char) -> Option<(Code, bool)> {
+    const LETTERS: [Code; 26] = [
+        Code::KEY_A,
+        Code::KEY_B,
+        Code::KEY_C,
+        Code::KEY_D,
+        Code::KEY_E,
+        Code::KEY_F,
+        Code::KEY_G,
+        Code::KEY_H,
+        Code::KEY_I,
+        Code::KEY_J,
+        Code::KEY_K,
+        Code::KEY_L,
+        Code::KEY_M,
+        Code::KEY_N,
+        Code::KEY_O,
+        Code::KEY_P,
+        Code::KEY_Q,
+        Code::KEY_R,
+        Code::KEY_S,
+        Code::KEY_T,
+        Code::KEY_U,
+        Code::KEY_V,
+        Code::KEY_W,
+        Code::KEY_X,
+        Code::KEY_Y,
+        Code::KEY_Z,
+    ];
+    const DIGITS: [Code; 10] = [
+        Code::KEY_0,
+        Code::KEY_1,
+        Code::KEY_2,
+        Code::KEY_3,
+        Code::KEY_4,
+        Code::KEY_5,
+        Code::KEY_6,
+        Code::KEY_7,
+        Code::KEY_8,
+        Code::KEY_9,
+    ];
+    const SHIFTED: &str = "~!@#$%^&*()_+{}:\"|<>?";
+    const UNSHIFTED: &str = "`1234567890-=[];'\\,./";
+    let shift = c.is_ascii_uppercase() || SHIFTED.contains(c);
+    let base = match SHIFTED.chars().position(|shifted| shifted == c) {
+        Some(i) => UNSHIFTED.chars().nth(i)?,
+        None => c.to_ascii_lowercase(),
+    };
+    let code = match base {
+        c @ 'a'..='z' => LETTERS[c as usize - 'a' as usize],
+        c @ '0'..='9' => DIGITS[c as usize - '0' as usize],
+        ' ' => Code::KEY_SPACE,
+        '\n' => Code::KEY_ENTER,
+        '\t' => Code::KEY_TAB,
+        '-' => Code::KEY_MINUS,
+        '=' => Code::KEY_EQUAL,
+        '[' => Code::KEY_LEFTBRACE,
+        ']' => Code::KEY_RIGHTBRACE,
+        ';' => Code::KEY_SEMICOLON,
+        '\'' => Code::KEY_APOSTROPHE,
+        '`' => Code::KEY_GRAVE,
+        '\\' => Code::KEY_BACKSLASH,
+        ',' => Code::KEY_COMMA,
+        '.' => Code::KEY_DOT,
+        '/' => Code::KEY_SLASH,
+        _ => return None,
+    };
+    Some((code, shift))
+}
+
+#[test]
+fn key_codes() {
+    assert_eq!(key_code(Key::Layout('w')), Some(Code::KEY_W));
+    assert_eq!(key_code(Key::Layout('7')), Some(Code::KEY_7));
+    assert_eq!(key_code(Key::Layout('/')), Some(Code::KEY_SLASH));
+    assert_eq!(key_code(Key::Layout('é')), None);
+    assert_eq!(key_code(Key::Meta), Some(Code::KEY_LEFTMETA));
+    assert_eq!(key_code(Key::Raw(0x11)), Some(Code::KEY_W));
+
+    // 大文字と記号はシフトを押して打つ
+    assert_eq!(layout_code('a'), Some((Code::KEY_A, false)));
+    assert_eq!(layout_code('A'), Some((Code::KEY_A, true)));
+    assert_eq!(layout_code('!'), Some((Code::KEY_1, true)));
+    assert_eq!(layout_code('@'), Some((Code::KEY_2, true)));
+    assert_eq!(layout_code(':'), Some((Code::KEY_SEMICOLON, true)));
+    assert_eq!(layout_code('"'), Some((Code::KEY_APOSTROPHE, true)));
+    assert_eq!(layout_code('|'), Some((Code::KEY_BACKSLASH, true)));
+    assert_eq!(layout_code('?'), Some((Code::KEY_SLASH, true)));
+    assert_eq!(layout_code('\n'), Some((Code::KEY_ENTER, false)));
+    assert_eq!(layout_code('é'), None);
+}